@@ -0,0 +1,89 @@
+use git2::{AttrCheckFlags, AttrValue, Repository};
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Numbers the temporary files handed to textconv commands
+static TEXTCONV_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// How the `diff` gitattribute says a path is compared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffDriver {
+    /// Line by line, unless the content looks binary
+    Default,
+    /// Never line by line: `-diff`, `binary`, or a driver with `diff.<driver>.binary` set
+    Binary,
+    /// Line by line after converting the content with the driver's `diff.<driver>.textconv` command
+    Textconv(String),
+}
+
+/// Diff driver of a path, from the working tree's gitattributes (then the index's) and the repository config
+pub fn diff_driver(repo: &Repository, path: &str) -> DiffDriver {
+    let attr = repo
+        .get_attr(Path::new(path), "diff", AttrCheckFlags::FILE_THEN_INDEX)
+        .unwrap_or(None);
+    let driver = match AttrValue::from_string(attr) {
+        AttrValue::False => return DiffDriver::Binary,
+        AttrValue::String(driver) => driver.to_string(),
+        _ => return DiffDriver::Default,
+    };
+
+    let Ok(config) = repo.config() else {
+        return DiffDriver::Default;
+    };
+    if let Ok(command) = config.get_string(&format!("diff.{driver}.textconv")) {
+        return DiffDriver::Textconv(command);
+    }
+    if config.get_bool(&format!("diff.{driver}.binary")).unwrap_or(false) {
+        return DiffDriver::Binary;
+    }
+    DiffDriver::Default
+}
+
+/// Convert content with a textconv command the way git runs it: through the shell, from the top of
+/// the working tree, with the path of a temporary file holding the content appended to the command
+pub fn textconv(repo: &Repository, command: &str, path: &str, content: &[u8]) -> Result<Vec<u8>, String> {
+    // Keep the extension, some converters go by it
+    let extension = Path::new(path)
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let file = std::env::temp_dir().join(format!(
+        "difference-textconv-{}-{}{}",
+        std::process::id(),
+        TEXTCONV_FILES.fetch_add(1, Ordering::Relaxed),
+        extension
+    ));
+    std::fs::write(&file, content).map_err(|e| e.to_string())?;
+
+    #[cfg(target_os = "windows")]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", &format!("{} \"{}\"", command, file.display())]);
+        shell
+    };
+
+    #[cfg(not(target_os = "windows"))]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", &format!("{} \"$@\"", command), command]).arg(&file);
+        shell
+    };
+
+    if let Some(workdir) = repo.workdir() {
+        shell.current_dir(workdir);
+    }
+    let output = shell.output();
+    let _ = std::fs::remove_file(&file);
+
+    let output = output.map_err(|e| format!("Failed to run textconv command '{}': {}", command, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Textconv command '{}' failed for {}: {}",
+            command,
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
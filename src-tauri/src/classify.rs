@@ -0,0 +1,148 @@
+use git2::{AttrCheckFlags, AttrValue, Repository};
+use std::path::Path;
+
+/// Dependency lockfiles, matched on the file name
+const LOCKFILES: &[&str] = &[
+    "Cargo.lock",
+    "bun.lock",
+    "bun.lockb",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "deno.lock",
+    "composer.lock",
+    "Gemfile.lock",
+    "Podfile.lock",
+    "Pipfile.lock",
+    "poetry.lock",
+    "uv.lock",
+    "pdm.lock",
+    "go.sum",
+    "go.work.sum",
+    "flake.lock",
+    "mix.lock",
+    "pubspec.lock",
+    "Package.resolved",
+    "packages.lock.json",
+    "gradle.lockfile",
+    "MODULE.bazel.lock",
+];
+
+/// Directories holding third-party code checked into the repository
+const VENDORED_DIRS: &[&str] = &["vendor", "vendored", "node_modules", "third_party", "third-party", "thirdparty"];
+
+/// Directories that hold build output or other generated files wherever they appear. Names that are
+/// just as often hand-written source (`build`, `out`, `target`) are left to `linguist-generated`.
+const GENERATED_DIRS: &[&str] = &["dist", ".next", ".nuxt", ".svelte-kit", "__snapshots__"];
+
+/// File name endings of generated files
+const GENERATED_SUFFIXES: &[&str] = &[
+    ".snap",
+    ".min.js",
+    ".min.css",
+    ".js.map",
+    ".css.map",
+    ".pb.go",
+    "_pb2.py",
+    "_pb2.pyi",
+    ".pb.h",
+    ".pb.cc",
+    ".g.dart",
+    ".freezed.dart",
+    ".designer.cs",
+];
+
+const TEST_DIRS: &[&str] = &["test", "tests", "__tests__", "spec", "specs", "testdata", "fixtures"];
+
+const DOC_DIRS: &[&str] = &["doc", "docs", "documentation"];
+
+const DOC_EXTENSIONS: &[&str] = &["md", "mdx", "markdown", "rst", "adoc", "asciidoc", "txt"];
+
+/// What kind of file a path is, so low-value files can be set apart from the source changes:
+/// "generated", "vendored", "lockfile", "test", "documentation" or "source".
+/// The `linguist-generated`, `linguist-vendored` and `linguist-documentation` gitattributes
+/// take precedence over the built-in path patterns, in either direction.
+pub fn classify(repo: &Repository, path: &str) -> &'static str {
+    let generated = linguist_attr(repo, path, "linguist-generated");
+    let vendored = linguist_attr(repo, path, "linguist-vendored");
+    let documentation = linguist_attr(repo, path, "linguist-documentation");
+
+    if generated == Some(true) {
+        return "generated";
+    }
+    if vendored == Some(true) {
+        return "vendored";
+    }
+    if documentation == Some(true) {
+        return "documentation";
+    }
+
+    let path = Path::new(path);
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let lower_name = name.to_lowercase();
+    let dirs: Vec<String> = path
+        .parent()
+        .into_iter()
+        .flat_map(|parent| parent.iter())
+        .map(|dir| dir.to_string_lossy().to_lowercase())
+        .collect();
+    let in_dir = |names: &[&str]| dirs.iter().any(|dir| names.contains(&dir.as_str()));
+
+    if generated.is_none() && LOCKFILES.contains(&name.as_str()) {
+        return "lockfile";
+    }
+    if vendored.is_none() && in_dir(VENDORED_DIRS) {
+        return "vendored";
+    }
+    if generated.is_none()
+        && (in_dir(GENERATED_DIRS) || GENERATED_SUFFIXES.iter().any(|suffix| lower_name.ends_with(suffix)))
+    {
+        return "generated";
+    }
+    if is_test(&name) || in_dir(TEST_DIRS) {
+        return "test";
+    }
+    if documentation.is_none() && is_documentation(&lower_name, in_dir(DOC_DIRS)) {
+        return "documentation";
+    }
+    "source"
+}
+
+/// A linguist attribute set (`attr`, `attr=true`) or unset (`-attr`, `attr=false`), `None` if not specified
+fn linguist_attr(repo: &Repository, path: &str, name: &str) -> Option<bool> {
+    let value = repo
+        .get_attr(Path::new(path), name, AttrCheckFlags::FILE_THEN_INDEX)
+        .unwrap_or(None);
+    match AttrValue::from_string(value) {
+        AttrValue::True => Some(true),
+        AttrValue::False => Some(false),
+        AttrValue::String(value) => Some(value != "false"),
+        _ => None,
+    }
+}
+
+/// Test files by the naming conventions of common test runners
+fn is_test(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name);
+    let lower_stem = stem.to_lowercase();
+    let lower_name = name.to_lowercase();
+
+    lower_stem.starts_with("test_")
+        || lower_stem.ends_with("_test")
+        || lower_stem.ends_with("_tests")
+        || lower_stem.ends_with("_spec")
+        || lower_name.contains(".test.")
+        || lower_name.contains(".spec.")
+        // FooTest.java, FooTests.cs
+        || (stem.len() > 5 && (stem.ends_with("Test") || stem.ends_with("Tests")))
+}
+
+fn is_documentation(name: &str, in_doc_dir: bool) -> bool {
+    let stem = name.split('.').next().unwrap_or(name);
+    let extension = name.rsplit_once('.').map(|(_, ext)| ext).unwrap_or("");
+
+    matches!(stem, "readme" | "changelog" | "changes" | "contributing" | "license" | "licence" | "authors")
+        || (extension != "txt" && DOC_EXTENSIONS.contains(&extension))
+        || (in_doc_dir && DOC_EXTENSIONS.contains(&extension))
+}
@@ -0,0 +1,412 @@
+use crate::git_ops::DiffLine;
+use std::path::Path;
+
+/// How comments are written in a language
+pub struct CommentSyntax {
+    /// Markers that comment out the rest of the line
+    line: &'static [&'static str],
+    /// Opening and closing markers of block comments
+    block: &'static [(&'static str, &'static str)],
+    /// Blocks that only count as comments when nothing but whitespace comes before them on the line,
+    /// like Python docstrings: the same string literal after an `=` is code
+    leading_block: &'static [(&'static str, &'static str)],
+}
+
+impl CommentSyntax {
+    const fn new(line: &'static [&'static str], block: &'static [(&'static str, &'static str)]) -> Self {
+        Self {
+            line,
+            block,
+            leading_block: &[],
+        }
+    }
+}
+
+static C_LIKE: CommentSyntax = CommentSyntax::new(&["//"], &[("/*", "*/")]);
+static CSS: CommentSyntax = CommentSyntax::new(&[], &[("/*", "*/")]);
+static HASH: CommentSyntax = CommentSyntax::new(&["#"], &[]);
+static INI: CommentSyntax = CommentSyntax::new(&[";", "#"], &[]);
+static PYTHON: CommentSyntax = CommentSyntax {
+    line: &["#"],
+    block: &[],
+    leading_block: &[("\"\"\"", "\"\"\""), ("'''", "'''")],
+};
+static RUBY: CommentSyntax = CommentSyntax {
+    line: &["#"],
+    block: &[],
+    leading_block: &[("=begin", "=end")],
+};
+static SQL: CommentSyntax = CommentSyntax::new(&["--"], &[("/*", "*/")]);
+// The block opener has to be tried before the line marker it starts with
+static LUA: CommentSyntax = CommentSyntax::new(&["--"], &[("--[[", "]]")]);
+static HASKELL: CommentSyntax = CommentSyntax::new(&["--"], &[("{-", "-}")]);
+static DASHES: CommentSyntax = CommentSyntax::new(&["--"], &[]);
+static LISP: CommentSyntax = CommentSyntax::new(&[";"], &[]);
+static PERCENT: CommentSyntax = CommentSyntax::new(&["%"], &[]);
+static MARKUP: CommentSyntax = CommentSyntax::new(&[], &[("<!--", "-->")]);
+static VISUAL_BASIC: CommentSyntax = CommentSyntax::new(&["'", "rem"], &[]);
+static BATCH: CommentSyntax = CommentSyntax::new(&["rem", "@rem", "::"], &[]);
+static OCAML: CommentSyntax = CommentSyntax::new(&[], &[("(*", "*)")]);
+static FSHARP: CommentSyntax = CommentSyntax::new(&["//"], &[("(*", "*)")]);
+static POWERSHELL: CommentSyntax = CommentSyntax::new(&["#"], &[("<#", "#>")]);
+static JULIA: CommentSyntax = CommentSyntax::new(&["#"], &[("#=", "=#")]);
+static NIX: CommentSyntax = CommentSyntax::new(&["#"], &[("/*", "*/")]);
+static HCL: CommentSyntax = CommentSyntax::new(&["#", "//"], &[("/*", "*/")]);
+static FORTRAN: CommentSyntax = CommentSyntax::new(&["!"], &[]);
+
+/// Comment syntax by file extension. Languages without comments (JSON) or whose markers are also
+/// common code (PHP 8 attributes start with `#`) are left out or kept to the unambiguous markers.
+const EXTENSIONS: &[(&[&str], &CommentSyntax)] = &[
+    (
+        &[
+            "rs", "c", "h", "cc", "cpp", "cxx", "hpp", "hh", "hxx", "m", "mm", "cs", "java", "js", "jsx", "mjs", "cjs",
+            "ts", "tsx", "mts", "cts", "go", "swift", "kt", "kts", "scala", "sc", "dart", "php", "groovy", "gradle",
+            "zig", "proto", "jsonc", "json5", "scss", "less", "sass", "sol", "glsl", "hlsl", "wgsl", "v", "d",
+        ],
+        &C_LIKE,
+    ),
+    (&["css"], &CSS),
+    (
+        &[
+            "sh", "bash", "zsh", "ksh", "fish", "yaml", "yml", "toml", "pl", "pm", "r", "cmake", "dockerfile", "mk",
+            "mak", "ex", "exs", "cr", "nim", "coffee", "properties", "gitignore", "gitattributes", "env",
+        ],
+        &HASH,
+    ),
+    (&["ini", "cfg"], &INI),
+    (&["py", "pyi", "pyw", "pyx"], &PYTHON),
+    (&["rb", "rake", "gemspec"], &RUBY),
+    (&["sql"], &SQL),
+    (&["lua"], &LUA),
+    (&["hs", "elm", "purs"], &HASKELL),
+    (&["ada", "adb", "ads", "vhd", "vhdl"], &DASHES),
+    (&["lisp", "lsp", "el", "clj", "cljs", "cljc", "edn", "scm", "ss", "rkt", "fnl", "asm"], &LISP),
+    (&["erl", "hrl", "tex", "sty"], &PERCENT),
+    (&["html", "htm", "xhtml", "xml", "svg", "xsl", "xslt", "vue", "svelte", "md", "markdown", "mdx"], &MARKUP),
+    (&["vb", "vbs", "bas"], &VISUAL_BASIC),
+    (&["bat", "cmd"], &BATCH),
+    (&["ml", "mli"], &OCAML),
+    (&["fs", "fsi", "fsx"], &FSHARP),
+    (&["ps1", "psm1", "psd1"], &POWERSHELL),
+    (&["jl"], &JULIA),
+    (&["nix"], &NIX),
+    (&["tf", "tfvars", "hcl"], &HCL),
+    (&["f90", "f95", "f03", "f08"], &FORTRAN),
+];
+
+/// Comment syntax by file name, for files without a telling extension
+const FILE_NAMES: &[(&[&str], &CommentSyntax)] = &[
+    (
+        &[
+            "Makefile", "GNUmakefile", "makefile", "Dockerfile", "Containerfile", "CMakeLists.txt", ".gitignore",
+            ".gitattributes", ".dockerignore", ".editorconfig", ".env", ".bashrc", ".zshrc", ".profile", "BUILD",
+            "WORKSPACE", "Procfile",
+        ],
+        &HASH,
+    ),
+    (&["Gemfile", "Rakefile", "Vagrantfile", "Podfile", "Brewfile"], &RUBY),
+    (&["Jenkinsfile"], &C_LIKE),
+];
+
+/// Comment syntax by shebang interpreter, for scripts without an extension
+const INTERPRETERS: &[(&str, &CommentSyntax)] = &[
+    ("python", &PYTHON),
+    ("ruby", &RUBY),
+    ("node", &C_LIKE),
+    ("deno", &C_LIKE),
+    ("bun", &C_LIKE),
+    ("lua", &LUA),
+    ("pwsh", &POWERSHELL),
+    ("sh", &HASH),
+    ("bash", &HASH),
+    ("zsh", &HASH),
+    ("ksh", &HASH),
+    ("dash", &HASH),
+    ("fish", &HASH),
+    ("perl", &HASH),
+    ("Rscript", &HASH),
+];
+
+/// Comment syntax of a file, from its name or extension, else from the shebang on its first line.
+/// `None` for languages without a known comment syntax.
+pub fn syntax_for(path: &str, first_line: Option<&str>) -> Option<&'static CommentSyntax> {
+    let path = Path::new(path);
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let extension = path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+
+    FILE_NAMES
+        .iter()
+        .find(|(names, _)| names.contains(&name.as_ref()))
+        .or_else(|| {
+            let extension = extension?;
+            EXTENSIONS.iter().find(|(extensions, _)| extensions.contains(&extension.as_str()))
+        })
+        .map(|&(_, syntax)| syntax)
+        .or_else(|| shebang_syntax(first_line?))
+}
+
+/// `#!/bin/sh`, `#!/usr/bin/env python3`, `#!/usr/bin/env -S node --flag`
+fn shebang_syntax(line: &str) -> Option<&'static CommentSyntax> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-'))?;
+    }
+
+    INTERPRETERS
+        .iter()
+        .find(|(name, _)| {
+            // python3, python3.12
+            interpreter
+                .strip_prefix(name)
+                .is_some_and(|version| version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+        })
+        .map(|&(_, syntax)| syntax)
+}
+
+/// Block comment or string literal a line ends inside of, which the next line continues
+enum Open {
+    /// Block comment, with its closing marker
+    Comment(&'static str),
+    /// String literal, with the delimiter that closes it
+    Literal(Literal),
+}
+
+struct Literal {
+    close: String,
+    /// A backslash keeps the next character from closing the literal; not so in Rust raw strings
+    escapes: bool,
+}
+
+impl Literal {
+    fn new(close: &str, escapes: bool) -> Self {
+        Self {
+            close: close.to_string(),
+            escapes,
+        }
+    }
+
+    /// Byte offset just past the delimiter closing the literal, `None` if it stays open
+    fn end(&self, text: &str) -> Option<usize> {
+        let mut escaped = false;
+        for (idx, c) in text.char_indices() {
+            if escaped {
+                escaped = false;
+            } else if text[idx..].starts_with(self.close.as_str()) {
+                return Some(idx + self.close.len());
+            } else if c == '\\' && self.escapes {
+                escaped = true;
+            }
+        }
+        None
+    }
+}
+
+/// Block comment and string literal state of one side of a diff, carried from a line to the next
+#[derive(Default)]
+struct SideState {
+    /// Line number the state applies to; other lines start over outside of comments and strings
+    next_lineno: Option<u32>,
+    open: Option<Open>,
+}
+
+impl SideState {
+    fn scan(&mut self, syntax: &CommentSyntax, lineno: u32, content: &str) -> bool {
+        if self.next_lineno != Some(lineno) {
+            self.open = None;
+        }
+        let (is_comment, open) = scan_line(syntax, content, self.open.take());
+        self.open = open;
+        self.next_lineno = Some(lineno + 1);
+        is_comment
+    }
+}
+
+/// For each line of a diff, whether it holds nothing but comments and whitespace in its language.
+/// Block comments, docstrings and string literals are followed across the lines of each side, as long
+/// as the line numbers are consecutive. Without a comment syntax only blank lines count.
+pub fn comment_lines(syntax: Option<&CommentSyntax>, lines: &[DiffLine]) -> Vec<bool> {
+    let Some(syntax) = syntax else {
+        return lines.iter().map(|line| line.content.trim().is_empty()).collect();
+    };
+
+    let mut old = SideState::default();
+    let mut new = SideState::default();
+    lines
+        .iter()
+        .map(|line| {
+            let old_comment = line.old_lineno.map(|lineno| old.scan(syntax, lineno, &line.content));
+            let new_comment = line.new_lineno.map(|lineno| new.scan(syntax, lineno, &line.content));
+            match line.line_type.as_str() {
+                "delete" => old_comment,
+                _ => new_comment.or(old_comment),
+            }
+            .unwrap_or(false)
+        })
+        .collect()
+}
+
+/// Whether a line holds nothing but comments and whitespace when it starts inside `open` (or outside
+/// of comments and strings), and what it ends inside of
+fn scan_line(syntax: &CommentSyntax, line: &str, mut open: Option<Open>) -> (bool, Option<Open>) {
+    let mut rest = line;
+    let mut code = false;
+    loop {
+        match open.take() {
+            Some(Open::Comment(close)) => {
+                let Some(end) = rest.find(close) else {
+                    return (!code, Some(Open::Comment(close)));
+                };
+                rest = &rest[end + close.len()..];
+            }
+            // The inside of a string literal is code, whatever it looks like
+            Some(Open::Literal(literal)) => {
+                let Some(end) = literal.end(rest) else {
+                    return (false, Some(Open::Literal(literal)));
+                };
+                code = true;
+                rest = &rest[end..];
+            }
+            None => {}
+        }
+
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return (!code, None);
+        }
+
+        let leading = if code { &[][..] } else { syntax.leading_block };
+        if let Some(&(open_marker, close)) = syntax
+            .block
+            .iter()
+            .chain(leading)
+            .find(|(open_marker, _)| starts_with_marker(rest, open_marker))
+        {
+            rest = &rest[open_marker.len()..];
+            open = Some(Open::Comment(close));
+            continue;
+        }
+        if syntax.line.iter().any(|marker| starts_with_marker(rest, marker)) {
+            return (!code, None);
+        }
+
+        code = true;
+        let (after, literal) = skip_code(rest);
+        rest = after;
+        open = literal.map(Open::Literal);
+    }
+}
+
+/// Markers made of letters (`rem`, `=begin`) match case-insensitively and only as whole words
+fn starts_with_marker(text: &str, marker: &str) -> bool {
+    let Some(head) = text.get(..marker.len()) else {
+        return false;
+    };
+    if !head.eq_ignore_ascii_case(marker) {
+        return false;
+    }
+    !marker.ends_with(|c: char| c.is_ascii_alphanumeric())
+        || !text[marker.len()..].starts_with(|c: char| c.is_alphanumeric() || c == '_')
+}
+
+/// Step over the next piece of code: a whole string literal, so that comment markers inside it are
+/// not taken for comments, or a single character. A literal that can span lines (`"`, backticks,
+/// triple quotes, Rust raw strings) and is still open at the end of the line comes back, for the next
+/// line to continue inside it. An unterminated single quote (a Rust lifetime) takes the rest of the
+/// line, which errs on the side of finding code.
+fn skip_code(text: &str) -> (&str, Option<Literal>) {
+    let (start, literal) = if let Some(triple) = ["\"\"\"", "'''"].into_iter().find(|q| text.starts_with(q)) {
+        (triple.len(), Literal::new(triple, true))
+    } else if let Some(raw) = raw_string_start(text) {
+        let hashes = raw - 2;
+        (raw, Literal::new(&format!("\"{}", "#".repeat(hashes)), false))
+    } else {
+        let Some(quote) = text.chars().next() else {
+            return (text, None);
+        };
+        if !matches!(quote, '"' | '\'' | '`') {
+            return (&text[quote.len_utf8()..], None);
+        }
+        (1, Literal::new(&text[..1], true))
+    };
+
+    let rest = &text[start..];
+    match literal.end(rest) {
+        Some(end) => (&rest[end..], None),
+        None if literal.close != "'" => ("", Some(literal)),
+        None => ("", None),
+    }
+}
+
+/// Length of the opening of a Rust raw string (`r"`, `r#"`), if the text starts with one
+fn raw_string_start(text: &str) -> Option<usize> {
+    let hashes = text.strip_prefix('r')?.bytes().take_while(|&b| b == b'#').count();
+    (text.as_bytes().get(1 + hashes) == Some(&b'"')).then_some(hashes + 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Diff lines from `(line_type, content)` pairs, numbered the way a hunk numbers them
+    fn diff_lines(lines: &[(&str, &str)]) -> Vec<DiffLine> {
+        let (mut old, mut new) = (0, 0);
+        lines
+            .iter()
+            .map(|&(line_type, content)| {
+                let old_lineno = (line_type != "add").then(|| {
+                    old += 1;
+                    old
+                });
+                let new_lineno = (line_type != "delete").then(|| {
+                    new += 1;
+                    new
+                });
+                DiffLine {
+                    content: format!("{}\n", content),
+                    line_type: line_type.to_string(),
+                    old_lineno,
+                    new_lineno,
+                    highlights: Vec::new(),
+                    moved: None,
+                    tokens: Vec::new(),
+                    cosmetic_reason: None,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn comment_markers_inside_multi_line_rust_string_are_code() {
+        let lines = diff_lines(&[
+            ("context", "const Q: &str = \""),
+            ("context", "select *"),
+            ("delete", "// from users"),
+            ("add", "// from admins"),
+            ("context", "\";"),
+        ]);
+        let comments = comment_lines(syntax_for("query.rs", None), &lines);
+        assert_eq!(comments, [false; 5]);
+    }
+
+    #[test]
+    fn comment_markers_inside_assigned_triple_quoted_string_are_code() {
+        let lines = diff_lines(&[
+            ("context", "x = \"\"\""),
+            ("delete", "# keep admins"),
+            ("add", "# drop admins"),
+            ("context", "\"\"\""),
+        ]);
+        let comments = comment_lines(syntax_for("policy.py", None), &lines);
+        assert_eq!(comments, [false; 4]);
+    }
+
+    #[test]
+    fn leading_docstrings_and_block_comments_still_count() {
+        let python = diff_lines(&[("context", "\"\"\""), ("add", "Docs"), ("context", "\"\"\"")]);
+        assert_eq!(comment_lines(syntax_for("lib.py", None), &python), [true; 3]);
+
+        let rust = diff_lines(&[("context", "let s = r#\"/*\"#; /*"), ("add", "note"), ("context", "*/")]);
+        assert_eq!(comment_lines(syntax_for("lib.rs", None), &rust), [false, true, true]);
+    }
+}
@@ -0,0 +1,89 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// How far into a file to look for NUL bytes, same as git's binary check
+const SNIFF_LEN: usize = 8000;
+
+/// File content decoded to UTF-8
+pub struct DecodedText {
+    pub text: String,
+    /// Encoding the content is stored in, e.g. "UTF-8", "UTF-16LE", "windows-1252"
+    pub encoding: &'static str,
+    /// Line ending convention of the stored content
+    pub line_ending: Option<&'static str>,
+    /// `text` differs from the raw bytes, so a byte-wise diff of the content would be wrong
+    pub converted: bool,
+}
+
+/// Decode file content to text, or `None` if it looks binary. UTF-16 is recognized from its BOM,
+/// or from the NUL bytes mostly-ASCII text leaves in every other position; content that is not
+/// valid UTF-8 is decoded with the legacy encoding it most likely uses.
+pub fn decode(content: &[u8]) -> Option<DecodedText> {
+    let encoding = detect(content)?;
+
+    let (text, converted) = if encoding == UTF_8 {
+        // A UTF-8 BOM stays part of the text, as in git's own diff
+        (String::from_utf8_lossy(content).to_string(), false)
+    } else {
+        let (text, _) = encoding.decode_with_bom_removal(content);
+        (text.to_string(), true)
+    };
+
+    Some(DecodedText {
+        line_ending: line_ending(&text),
+        text,
+        encoding: encoding.name(),
+        converted,
+    })
+}
+
+fn detect(content: &[u8]) -> Option<&'static Encoding> {
+    if let Some((encoding, _)) = Encoding::for_bom(content) {
+        return Some(encoding);
+    }
+
+    let head = &content[..content.len().min(SNIFF_LEN)];
+    if head.contains(&0) {
+        return utf16_without_bom(head);
+    }
+    if std::str::from_utf8(content).is_ok() {
+        return Some(UTF_8);
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(content, true);
+    Some(detector.guess(None, false))
+}
+
+/// UTF-16 byte order of BOM-less content, judged by which half of each code unit is NUL
+fn utf16_without_bom(head: &[u8]) -> Option<&'static Encoding> {
+    let units = head.len() / 2;
+    let nuls_at = |first: usize| head.iter().skip(first).step_by(2).filter(|&&b| b == 0).count();
+    let (even, odd) = (nuls_at(0), nuls_at(1));
+
+    if even == 0 && odd * 10 >= units * 9 {
+        Some(UTF_16LE)
+    } else if odd == 0 && even * 10 >= units * 9 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Line ending convention of a text: "lf", "crlf" or "mixed", `None` without any line breaks
+pub fn line_ending(text: &str) -> Option<&'static str> {
+    let crlf = text.matches("\r\n").count();
+    let lf = text.matches('\n').count() - crlf;
+
+    match (lf, crlf) {
+        (0, 0) => None,
+        (_, 0) => Some("lf"),
+        (0, _) => Some("crlf"),
+        _ => Some("mixed"),
+    }
+}
+
+/// Convert CRLF line endings to LF
+pub fn normalize_line_endings(text: &str) -> String {
+    text.replace("\r\n", "\n")
+}
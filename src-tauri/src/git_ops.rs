@@ -1,7 +1,21 @@
-use git2::{Delta, DiffOptions, Repository, StatusOptions};
+use crate::attributes::{self, DiffDriver};
+use crate::classify;
+use crate::comments::{self, CommentSyntax};
+use crate::encoding::{self, DecodedText};
+use crate::histogram::{self, LineCompare};
+use crate::intraline::{self, IntralineMode};
+use crate::lfs::{self, LfsInfo};
+use crate::media::{self, BlobInfo};
+use crate::moved;
+use crate::syntax::{self, SyntaxToken};
+use crate::syntax_tree;
+use git2::{
+    Commit, Delta, DiffDelta, DiffFindOptions, DiffOptions, FileFavor, FileMode, Index, MergeOptions, Oid, Patch,
+    Repository, StatusOptions, Tree,
+};
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::RangeInclusive;
 use std::path::Path;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -11,14 +25,92 @@ pub struct ChangedFile {
     pub additions: i32,
     pub deletions: i32,
     pub is_cosmetic: bool,
+    /// Why the file is cosmetic, see `DiffHunk::cosmetic_reason`
+    pub cosmetic_reason: Option<String>,
+    /// Source path for renamed and copied files
+    pub old_path: Option<String>,
+    /// Content similarity (0-100) between `old_path` and `path` for renamed and copied files
+    pub similarity: Option<u16>,
+    /// Where the change lives: any of "committed", "staged", "unstaged", "untracked"
+    pub layers: Vec<String>,
+    /// Set when the path is a submodule rather than a regular file
+    pub submodule: Option<SubmoduleChange>,
+    /// File modes in git's octal notation ("100644", "100755", "120000" for symlinks, ...),
+    /// `None` on the side where the file does not exist
+    pub old_mode: Option<String>,
+    pub new_mode: Option<String>,
+    /// Encoding each side is stored in ("UTF-8", "UTF-16LE", "windows-1252", ...), `None` for binary
+    /// content and on the side where the file does not exist
+    pub old_encoding: Option<String>,
+    pub new_encoding: Option<String>,
+    /// Line ending convention of each side: "lf", "crlf" or "mixed"
+    pub old_line_ending: Option<String>,
+    pub new_line_ending: Option<String>,
+    /// The line ending convention differs between the sides
+    pub line_endings_changed: bool,
+    /// Set on the sides stored in Git LFS
+    pub old_lfs: Option<LfsInfo>,
+    pub new_lfs: Option<LfsInfo>,
+    /// What kind of file this is: "generated", "vendored", "lockfile", "test", "documentation" or "source"
+    pub classification: String,
+}
+
+/// The commits a submodule pointed at before and after a change
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubmoduleChange {
+    /// `None` when the submodule was added
+    pub old_oid: Option<String>,
+    /// `None` when the submodule was removed
+    pub new_oid: Option<String>,
+}
+
+/// The changed files of a comparison, along with what was actually compared
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangeSet {
+    pub files: Vec<ChangedFile>,
+    pub mode: CompareMode,
+    /// All zeros when the diff starts from nothing, for a range that begins at a root commit
+    pub base_oid: String,
+    pub head_oid: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileDiff {
     pub path: String,
+    pub old_path: Option<String>,
     pub hunks: Vec<DiffHunk>,
     pub is_binary: bool,
     pub is_cosmetic: bool,
+    /// Number of cosmetic hunks for each reason, over the whole diff
+    pub cosmetic_reasons: BTreeMap<String, u32>,
+    pub mode: CompareMode,
+    /// All zeros when the diff starts from nothing, like `ChangeSet::base_oid`
+    pub base_oid: String,
+    pub old_mode: Option<String>,
+    pub new_mode: Option<String>,
+    /// Link targets on the sides where the file is a symlink
+    pub old_symlink_target: Option<String>,
+    pub new_symlink_target: Option<String>,
+    pub old_encoding: Option<String>,
+    pub new_encoding: Option<String>,
+    pub old_line_ending: Option<String>,
+    pub new_line_ending: Option<String>,
+    pub line_endings_changed: bool,
+    /// Set on the sides stored in Git LFS, whose real content is diffed when it is available locally
+    pub old_lfs: Option<LfsInfo>,
+    pub new_lfs: Option<LfsInfo>,
+    /// Size of the whole diff, regardless of the window of it that was returned
+    pub total_hunks: u32,
+    pub total_lines: u32,
+    /// The diff exceeded `max_diff_lines` and only its totals were returned
+    pub is_truncated: bool,
+}
+
+/// Lines `offset..offset + limit` of a file diff, counting the lines of all hunks in order
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct DiffWindow {
+    pub offset: u32,
+    pub limit: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -29,6 +121,17 @@ pub struct DiffHunk {
     pub new_lines: u32,
     pub lines: Vec<DiffLine>,
     pub is_cosmetic: bool,
+    /// Why the hunk is cosmetic: "whitespace", "comment", "case", "rewrap", "line-ending", "moved",
+    /// "syntax" (same syntax tree), or "mixed" when its changed lines have different reasons
+    pub cosmetic_reason: Option<String>,
+    /// Every change in the hunk only converts line endings
+    pub line_endings_changed: bool,
+    /// "committed", "staged", "unstaged", "untracked", or "combined" in the combined view
+    pub layer: String,
+    /// Number of leading lines of the hunk left out because they fall before the requested window
+    pub line_offset: u32,
+    /// The commit that introduced the hunk, when diffing a selection of commits
+    pub commit: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,6 +140,192 @@ pub struct DiffLine {
     pub line_type: String,
     pub old_lineno: Option<u32>,
     pub new_lineno: Option<u32>,
+    /// Ranges that changed relative to the paired line on the other side
+    pub highlights: Vec<Highlight>,
+    /// For a line that was moved rather than changed, where its counterpart lives
+    pub moved: Option<MoveLink>,
+    /// Syntax highlighting of `content`, from parsing the whole side the line was taken from
+    pub tokens: Vec<SyntaxToken>,
+    /// Why the line counts as cosmetic, for the changed lines of cosmetic hunks
+    pub cosmetic_reason: Option<String>,
+}
+
+/// Location of the other end of a moved line: the added line for a deletion, the deleted line for an addition
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MoveLink {
+    /// Changed-file path the counterpart belongs to
+    pub path: String,
+    /// New-side line number for an added counterpart, old-side line number for a deleted one
+    pub lineno: u32,
+}
+
+/// A range within a line's content, in UTF-16 code units so it can slice JS strings directly
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct Highlight {
+    pub start: u32,
+    pub end: u32,
+}
+
+/// Which commit the left side of a comparison starts from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompareMode {
+    /// `base...head`: diff from the merge base, showing only what head introduced
+    #[default]
+    ThreeDot,
+    /// `base..head`: diff the two tips directly
+    TwoDot,
+    /// Like three-dot, but only the commits on head's first-parent chain count, so changes that
+    /// merges of the base brought in are left out
+    FirstParent,
+}
+
+/// Line diff algorithm
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffAlgorithm {
+    /// Whatever the repository's `diff.algorithm` config says, Myers if unset
+    #[default]
+    Auto,
+    Myers,
+    Minimal,
+    Patience,
+    /// Not available in libgit2; hunks are computed by the built-in implementation in `histogram`
+    Histogram,
+}
+
+/// How uncommitted work is presented when comparing against the working tree
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiffView {
+    /// Committed, staged and unstaged changes as separate layers
+    #[default]
+    Layered,
+    /// One diff from the base straight to the working tree
+    Combined,
+}
+
+/// Options shared by the changed-file and file-diff entry points
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CompareOptions {
+    pub mode: CompareMode,
+    pub view: DiffView,
+    /// Pair up deleted and added files as renames and copies
+    pub detect_renames: bool,
+    /// Minimum similarity (0-100) for a pair to count as a rename or copy
+    pub similarity_threshold: u16,
+    /// Unchanged lines shown around each hunk in file diffs
+    pub context_lines: u32,
+    /// Ignore all whitespace when comparing lines (`git diff -w`)
+    pub ignore_whitespace: bool,
+    /// Ignore changes in the amount of whitespace (`git diff -b`)
+    pub ignore_whitespace_change: bool,
+    /// Ignore whitespace at end of line (`git diff --ignore-space-at-eol`)
+    pub ignore_whitespace_eol: bool,
+    /// Ignore lines that are all blank (`git diff --ignore-blank-lines`)
+    pub ignore_blank_lines: bool,
+    /// Granularity of the intra-line highlights in file diffs
+    pub intraline: IntralineMode,
+    pub algorithm: DiffAlgorithm,
+    /// Mark blocks of lines that were moved within or between files (`git diff --color-moved`)
+    pub detect_moves: bool,
+    /// Treat moved lines as cosmetic when deciding whether a hunk or file is cosmetic
+    pub moves_are_cosmetic: bool,
+    /// File diffs with more lines than this only return their totals unless a window is requested; 0 for no limit
+    pub max_diff_lines: u32,
+    /// Only count the changes these commits of the compared range introduced, each against its first parent.
+    /// Empty for the whole range.
+    pub commits: Vec<String>,
+    /// Convert CRLF line endings to LF before diffing, so line ending conversions leave no hunks
+    pub normalize_line_endings: bool,
+    /// Attach syntax highlighting tokens to the lines of file diffs
+    pub syntax_highlight: bool,
+    /// Decide what is cosmetic by comparing syntax trees, for languages with a bundled grammar:
+    /// a change is cosmetic only when the trees are equal apart from comments and whitespace
+    pub syntax_aware_cosmetic: bool,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            mode: CompareMode::default(),
+            view: DiffView::default(),
+            detect_renames: true,
+            // Same default as git
+            similarity_threshold: 50,
+            context_lines: 3,
+            ignore_whitespace: false,
+            ignore_whitespace_change: false,
+            ignore_whitespace_eol: false,
+            ignore_blank_lines: false,
+            intraline: IntralineMode::default(),
+            algorithm: DiffAlgorithm::default(),
+            detect_moves: false,
+            moves_are_cosmetic: false,
+            max_diff_lines: 10_000,
+            commits: Vec::new(),
+            normalize_line_endings: false,
+            syntax_highlight: true,
+            syntax_aware_cosmetic: false,
+        }
+    }
+}
+
+/// What changed inside a submodule between two of its commits
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubmoduleSummary {
+    /// Working directory of the submodule, usable as `repo_path` for further comparisons
+    pub repo_path: String,
+    /// Commits the pointer moved forward over, oldest first
+    pub commits: Vec<CommitInfo>,
+    /// Commits the pointer moved back over, when the new commit does not contain the old one
+    pub removed_commits: Vec<CommitInfo>,
+    pub changes: ChangeSet,
+}
+
+/// A commit of the branch under review
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommitInfo {
+    pub oid: String,
+    pub short_oid: String,
+    pub summary: String,
+    pub author_name: String,
+    pub author_email: String,
+    /// Author time in seconds since the Unix epoch
+    pub time: i64,
+    /// Paths changed relative to the first parent
+    pub files: Vec<String>,
+}
+
+/// Both versions of a file as raw blobs. A side is `None` when the file does not exist there.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileBlobs {
+    pub path: String,
+    pub old_path: Option<String>,
+    pub old: Option<BlobInfo>,
+    pub new: Option<BlobInfo>,
+    /// Set on the sides stored in Git LFS; an unavailable object is described by its pointer file
+    pub old_lfs: Option<LfsInfo>,
+    pub new_lfs: Option<LfsInfo>,
+}
+
+/// Which side of a comparison a line range is taken from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffSide {
+    Old,
+    New,
+}
+
+/// A range of lines from one side of a file, for expanding the context around hunks
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileLines {
+    pub path: String,
+    pub side: DiffSide,
+    /// Total number of lines on the requested side
+    pub total_lines: u32,
+    pub lines: Vec<DiffLine>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -56,7 +345,7 @@ pub struct RepoInfo {
 
 fn delta_to_status(delta: Delta) -> &'static str {
     match delta {
-        Delta::Added => "added",
+        Delta::Added | Delta::Untracked => "added",
         Delta::Deleted => "deleted",
         Delta::Modified => "modified",
         Delta::Renamed => "renamed",
@@ -66,34 +355,300 @@ fn delta_to_status(delta: Delta) -> &'static str {
     }
 }
 
-/// Check if a line is a comment based on common patterns
-fn is_comment_line(line: &str) -> bool {
-    let trimmed = line.trim();
+/// Path a delta is reported under: the new path, or the old one for deletions
+fn delta_path(delta: &DiffDelta) -> String {
+    delta
+        .new_file()
+        .path()
+        .or_else(|| delta.old_file().path())
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Source path of a renamed or copied delta
+fn delta_old_path(delta: &DiffDelta) -> Option<String> {
+    match delta.status() {
+        Delta::Renamed | Delta::Copied => delta
+            .old_file()
+            .path()
+            .map(|p| p.to_string_lossy().to_string()),
+        _ => None,
+    }
+}
+
+/// Git's octal notation of a file mode, or `None` where the file does not exist
+fn mode_string(mode: FileMode) -> Option<String> {
+    (mode != FileMode::Unreadable).then(|| format!("{:06o}", u32::from(mode)))
+}
+
+/// Target of a symlink side of a delta, read from its blob or, on the working tree side, from the link itself
+fn symlink_target(repo: &Repository, file: &git2::DiffFile) -> Option<String> {
+    if file.mode() != FileMode::Link {
+        return None;
+    }
+
+    if let Ok(blob) = repo.find_blob(file.id()) {
+        return Some(String::from_utf8_lossy(blob.content()).to_string());
+    }
+
+    let path = repo.workdir()?.join(file.path()?);
+    std::fs::read_link(path)
+        .ok()
+        .map(|target| target.to_string_lossy().to_string())
+}
+
+/// Old and new commits of a submodule entry, or `None` if neither side of the delta is a submodule
+fn delta_submodule(delta: &DiffDelta) -> Option<SubmoduleChange> {
+    let (old, new) = (delta.old_file(), delta.new_file());
+    if old.mode() != FileMode::Commit && new.mode() != FileMode::Commit {
+        return None;
+    }
+
+    let oid = |file: &git2::DiffFile| {
+        (file.mode() == FileMode::Commit && !file.id().is_zero()).then(|| file.id().to_string())
+    };
+    Some(SubmoduleChange {
+        old_oid: oid(&old),
+        new_oid: oid(&new),
+    })
+}
+
+/// The algorithm to diff with, resolving `Auto` through the repository's `diff.algorithm` config
+fn effective_algorithm(repo: &Repository, options: &CompareOptions) -> DiffAlgorithm {
+    if options.algorithm != DiffAlgorithm::Auto {
+        return options.algorithm;
+    }
+
+    let configured = repo
+        .config()
+        .and_then(|config| config.get_string("diff.algorithm"))
+        .unwrap_or_default();
+
+    match configured.to_lowercase().as_str() {
+        "minimal" => DiffAlgorithm::Minimal,
+        "patience" => DiffAlgorithm::Patience,
+        "histogram" => DiffAlgorithm::Histogram,
+        _ => DiffAlgorithm::Myers,
+    }
+}
+
+/// libgit2 diff options carrying the content-related settings of a comparison
+fn diff_options(repo: &Repository, options: &CompareOptions) -> DiffOptions {
+    let algorithm = effective_algorithm(repo, options);
+
+    let mut diff_opts = DiffOptions::new();
+    diff_opts
+        .context_lines(options.context_lines)
+        .ignore_whitespace(options.ignore_whitespace)
+        .ignore_whitespace_change(options.ignore_whitespace_change)
+        .ignore_whitespace_eol(options.ignore_whitespace_eol)
+        .ignore_blank_lines(options.ignore_blank_lines)
+        .minimal(algorithm == DiffAlgorithm::Minimal)
+        // Patience is the closest libgit2 gets to histogram, for anything not recomputed by hand
+        .patience(matches!(algorithm, DiffAlgorithm::Patience | DiffAlgorithm::Histogram))
+        // A file turning into a symlink (or back) is one typechange rather than a deletion and an addition
+        .include_typechange(true);
+    diff_opts
+}
+
+/// Line comparison settings for the built-in histogram diff
+fn line_compare(options: &CompareOptions) -> LineCompare {
+    LineCompare {
+        ignore_whitespace: options.ignore_whitespace,
+        ignore_whitespace_change: options.ignore_whitespace_change,
+        ignore_whitespace_eol: options.ignore_whitespace_eol,
+        ignore_blank_lines: options.ignore_blank_lines,
+    }
+}
+
+/// Old and new contents of a delta, with LFS pointers replaced by their objects where available
+/// and textconv drivers applied
+struct DeltaContents {
+    /// `None` on a side where the file does not exist
+    old: Option<Vec<u8>>,
+    new: Option<Vec<u8>>,
+    old_lfs: Option<LfsInfo>,
+    new_lfs: Option<LfsInfo>,
+    /// Gitattributes rule out a line diff, or a textconv command failed
+    is_binary: bool,
+    /// Some side went through a textconv command
+    is_textconv: bool,
+}
+
+impl DeltaContents {
+    /// The contents differ from the blobs libgit2 diffs: an LFS object replaced its pointer,
+    /// or textconv converted a side
+    fn is_converted(&self) -> bool {
+        self.is_textconv || [&self.old_lfs, &self.new_lfs].into_iter().flatten().any(|lfs| lfs.available)
+    }
+}
+
+/// Read both sides of a delta. Working tree files have no blob yet and are read from disk.
+fn delta_contents(repo: &Repository, delta: &DiffDelta) -> DeltaContents {
+    let mut is_binary = false;
+    let mut is_textconv = false;
+
+    let read = |file: &git2::DiffFile| -> Vec<u8> {
+        // A link's content is its target, diffed as a one-line text
+        if file.mode() == FileMode::Link {
+            return symlink_target(repo, file).unwrap_or_default().into_bytes();
+        }
+        match repo.find_blob(file.id()) {
+            Ok(blob) => blob.content().to_vec(),
+            Err(_) => match (repo.workdir(), file.path()) {
+                (Some(workdir), Some(path)) => std::fs::read(workdir.join(path)).unwrap_or_default(),
+                _ => Vec::new(),
+            },
+        }
+    };
+
+    let mut convert = |file: &git2::DiffFile, content: Vec<u8>| -> Vec<u8> {
+        if file.mode() == FileMode::Link {
+            return content;
+        }
+        let path = file.path().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+        match attributes::diff_driver(repo, &path) {
+            DiffDriver::Default => content,
+            DiffDriver::Binary => {
+                is_binary = true;
+                content
+            }
+            DiffDriver::Textconv(command) => match attributes::textconv(repo, &command, &path, &content) {
+                Ok(converted) => {
+                    is_textconv = true;
+                    converted
+                }
+                Err(e) => {
+                    log::warn!("{}", e);
+                    is_binary = true;
+                    content
+                }
+            },
+        }
+    };
+
+    // Submodules have no content of their own here; their commits are reported separately. Neither
+    // does the link side of a file turning into a symlink (or back), whose target is reported separately.
+    let typechange = delta.status() == Delta::Typechange;
+    let has_content = |file: git2::DiffFile| match file.mode() {
+        FileMode::Commit => false,
+        FileMode::Link => !typechange,
+        _ => true,
+    };
+    let old_exists = !matches!(delta.status(), Delta::Added | Delta::Untracked) && has_content(delta.old_file());
+    let new_exists = delta.status() != Delta::Deleted && has_content(delta.new_file());
+
+    let (old_file, new_file) = (delta.old_file(), delta.new_file());
+    let [(old, old_lfs), (new, new_lfs)] = lfs::resolve(
+        repo,
+        old_exists.then(|| read(&old_file)),
+        new_exists.then(|| read(&new_file)),
+    );
+    DeltaContents {
+        old: old.map(|content| convert(&old_file, content)),
+        new: new.map(|content| convert(&new_file, content)),
+        old_lfs,
+        new_lfs,
+        is_binary,
+        is_textconv,
+    }
+}
+
+/// Decode one side of a file for diffing, converting CRLF to LF if the options ask for it
+fn side_text(content: &[u8], options: &CompareOptions) -> Option<DecodedText> {
+    let mut decoded = encoding::decode(content)?;
+    if options.normalize_line_endings && decoded.text.contains("\r\n") {
+        decoded.text = encoding::normalize_line_endings(&decoded.text);
+        decoded.converted = true;
+    }
+    Some(decoded)
+}
+
+/// Both sides of a delta as text; a side is `None` where the file does not exist
+struct DeltaText {
+    old: Option<DecodedText>,
+    new: Option<DecodedText>,
+}
+
+impl DeltaText {
+    fn old_text(&self) -> &str {
+        self.old.as_ref().map_or("", |side| side.text.as_str())
+    }
+
+    fn new_text(&self) -> &str {
+        self.new.as_ref().map_or("", |side| side.text.as_str())
+    }
+
+    /// Either side's text differs from its raw bytes
+    fn is_converted(&self) -> bool {
+        [&self.old, &self.new].into_iter().flatten().any(|side| side.converted)
+    }
+}
+
+/// Decoded text of both sides of a delta, or `None` if either side is binary
+fn delta_text(contents: &DeltaContents, options: &CompareOptions) -> Option<DeltaText> {
+    if contents.is_binary {
+        return None;
+    }
+
+    let side = |content: &Option<Vec<u8>>| match content {
+        Some(content) => side_text(content, options).map(Some),
+        None => Some(None),
+    };
+
+    Some(DeltaText {
+        old: side(&contents.old)?,
+        new: side(&contents.new)?,
+    })
+}
+
+/// Whether both sides have line breaks and use different conventions for them
+fn line_endings_differ(old: &Option<String>, new: &Option<String>) -> bool {
+    matches!((old, new), (Some(old), Some(new)) if old != new)
+}
 
-    // Empty lines are considered cosmetic
-    if trimmed.is_empty() {
-        return true;
+/// Run rename and copy detection on a diff according to the options
+fn find_similar(diff: &mut git2::Diff, options: &CompareOptions) -> Result<(), String> {
+    if !options.detect_renames {
+        return Ok(());
     }
 
-    // Single-line comments
-    if trimmed.starts_with("//") ||      // C, C++, JS, TS, Rust, Go, Java
-       trimmed.starts_with("#") ||       // Python, Ruby, Shell, YAML
-       trimmed.starts_with("--") ||      // SQL, Lua, Haskell
-       trimmed.starts_with(";") ||       // Lisp, Assembly, INI
-       trimmed.starts_with("*") ||       // JSDoc, block comment continuation
-       trimmed.starts_with("/*") ||      // C-style block comment start
-       trimmed.starts_with("*/") ||      // C-style block comment end
-       trimmed.starts_with("'") ||       // VB
-       trimmed.starts_with("\"\"\"") ||  // Python docstring
-       trimmed.starts_with("'''") ||     // Python docstring
-       trimmed.starts_with("<!--") ||    // HTML/XML
-       trimmed.starts_with("-->") ||     // HTML/XML
-       trimmed.starts_with("rem ") ||    // Batch
-       trimmed.to_lowercase().starts_with("rem ") {
-        return true;
+    let mut find_opts = DiffFindOptions::new();
+    find_opts
+        .renames(true)
+        .copies(true)
+        .rename_threshold(options.similarity_threshold)
+        .copy_threshold(options.similarity_threshold);
+
+    diff.find_similar(Some(&mut find_opts))
+        .map_err(|e| e.message().to_string())
+}
+
+/// Similarity of a rename/copy target to its source, as the share of source lines that survived a
+/// plain line diff of the two blobs. `None` for binary files, which have no lines to go by.
+fn rename_similarity(repo: &Repository, old_id: Oid, new_id: Oid) -> Option<u16> {
+    if old_id == new_id {
+        return Some(100);
+    }
+
+    let old = repo.find_blob(old_id).ok()?;
+    let new = repo.find_blob(new_id).ok()?;
+    if old.is_binary() || new.is_binary() {
+        return None;
+    }
+    let (_, additions, deletions) = Patch::from_blobs(&old, None, &new, None, None)
+        .and_then(|patch| patch.line_stats())
+        .ok()?;
+
+    let content = old.content();
+    let old_lines = content.split(|&b| b == b'\n').count() - usize::from(content.is_empty() || content.ends_with(b"\n"));
+    let kept = old_lines.saturating_sub(deletions);
+    let total = old_lines.max(kept + additions);
+    if total == 0 {
+        return Some(100);
     }
 
-    false
+    Some((kept * 100 / total) as u16)
 }
 
 /// Check if two lines differ only in whitespace
@@ -132,69 +687,183 @@ fn is_formatting_only_change(old_lines: &[&str], new_lines: &[&str]) -> bool {
     old_combined == new_combined
 }
 
-/// Analyze a hunk to determine if it's cosmetic
-fn analyze_hunk_cosmetic(lines: &[DiffLine]) -> bool {
-    let additions: Vec<&str> = lines
+/// Check if the changes of a hunk only convert line endings, pairing deletions and additions in order
+fn is_line_ending_only_change(lines: &[DiffLine]) -> bool {
+    let deletions: Vec<&str> = lines
         .iter()
-        .filter(|l| l.line_type == "add")
+        .filter(|l| l.line_type == "delete")
         .map(|l| l.content.as_str())
         .collect();
-
-    let deletions: Vec<&str> = lines
+    let additions: Vec<&str> = lines
         .iter()
-        .filter(|l| l.line_type == "delete")
+        .filter(|l| l.line_type == "add")
         .map(|l| l.content.as_str())
         .collect();
 
-    // If only additions or only deletions, check if all are comments/whitespace/empty
-    if additions.is_empty() && !deletions.is_empty() {
-        return deletions.iter().all(|l| is_comment_line(l));
+    !deletions.is_empty()
+        && deletions.len() == additions.len()
+        && deletions.iter().zip(&additions).all(|(old, new)| {
+            old != new && old.trim_end_matches(['\r', '\n']) == new.trim_end_matches(['\r', '\n'])
+        })
+}
+
+/// Why a hunk, or all changed lines of a file, counts as cosmetic
+struct Cosmetic {
+    /// The reason shared by all changed lines, or "mixed"
+    reason: &'static str,
+    /// Reason of each analyzed line, `None` for context lines
+    line_reasons: Vec<Option<&'static str>>,
+}
+
+impl Cosmetic {
+    /// `None` if there is no changed line to give a reason
+    fn from_lines(line_reasons: Vec<Option<&'static str>>) -> Option<Cosmetic> {
+        let mut reasons = line_reasons.iter().flatten();
+        let first = *reasons.next()?;
+        let reason = if reasons.all(|&reason| reason == first) { first } else { "mixed" };
+        Some(Cosmetic { reason, line_reasons })
     }
 
-    if deletions.is_empty() && !additions.is_empty() {
-        return additions.iter().all(|l| is_comment_line(l));
+    /// The same reason for every changed line
+    fn all_lines(lines: &[DiffLine], reason: &'static str) -> Option<Cosmetic> {
+        Cosmetic::from_lines(lines.iter().map(|l| (l.line_type != "context").then_some(reason)).collect())
     }
+}
 
-    // Check if it's a formatting-only change (content reformatted across lines)
-    // This handles Tailwind class reordering, line wrapping, etc.
-    if is_formatting_only_change(&deletions, &additions) {
-        return true;
+/// Reason a comment or blank line counts as cosmetic on its own
+fn comment_reason(line: &str) -> &'static str {
+    if line.trim().is_empty() {
+        "whitespace"
+    } else {
+        "comment"
+    }
+}
+
+/// Reason a deleted line and the added line replacing it count as cosmetic, `None` for a real change
+fn pair_reason(old: &str, new: &str, old_comment: bool, new_comment: bool) -> Option<&'static str> {
+    if old != new && old.trim_end_matches(['\r', '\n']) == new.trim_end_matches(['\r', '\n']) {
+        Some("line-ending")
+    } else if is_whitespace_only_change(old, new)
+        || is_indentation_only_change(old, new)
+        || is_trailing_whitespace_change(old, new)
+    {
+        Some("whitespace")
+    } else if old_comment && new_comment {
+        Some("comment")
+    } else if is_case_only_change(old, new) {
+        Some("case")
+    } else {
+        None
+    }
+}
+
+/// Analyze a hunk to determine if it's cosmetic, and why. With `ignore_moved`, lines that were
+/// only moved elsewhere are left out, so a pure move counts as cosmetic. Comments are recognized
+/// by the comment syntax of the file's language; without one only blank lines are.
+fn analyze_hunk_cosmetic(lines: &[DiffLine], comments: Option<&CommentSyntax>, ignore_moved: bool) -> Option<Cosmetic> {
+    let is_comment = comments::comment_lines(comments, lines);
+    let mut line_reasons = vec![None; lines.len()];
+    // Indexes of the changed lines, with whether each is a comment
+    let mut additions: Vec<(usize, bool)> = Vec::new();
+    let mut deletions: Vec<(usize, bool)> = Vec::new();
+
+    for (idx, (line, is_comment)) in lines.iter().zip(is_comment).enumerate() {
+        match line.line_type.as_str() {
+            "context" => {}
+            _ if ignore_moved && line.moved.is_some() => line_reasons[idx] = Some("moved"),
+            "add" => additions.push((idx, is_comment)),
+            _ => deletions.push((idx, is_comment)),
+        }
+    }
+    let content = |idx: usize| lines[idx].content.as_str();
+    let all_comments = additions.iter().chain(&deletions).all(|&(_, is_comment)| is_comment);
+
+    // If only additions or only deletions, check if all are comments/whitespace/empty
+    if additions.is_empty() || deletions.is_empty() {
+        if !all_comments {
+            return None;
+        }
+        for &(idx, _) in additions.iter().chain(&deletions) {
+            line_reasons[idx] = Some(comment_reason(content(idx)));
+        }
+        return Cosmetic::from_lines(line_reasons);
     }
 
     // If same number of additions and deletions, check pairwise
     if additions.len() == deletions.len() {
-        for (old, new) in deletions.iter().zip(additions.iter()) {
-            // Both are comments
-            if is_comment_line(old) && is_comment_line(new) {
-                continue;
-            }
-            // Whitespace-only change (any whitespace differs)
-            if is_whitespace_only_change(old, new) {
-                continue;
-            }
-            // Indentation-only change
-            if is_indentation_only_change(old, new) {
-                continue;
-            }
-            // Trailing whitespace change
-            if is_trailing_whitespace_change(old, new) {
-                continue;
-            }
-            // Case-only change
-            if is_case_only_change(old, new) {
-                continue;
+        let pair_reasons: Option<Vec<&'static str>> = deletions
+            .iter()
+            .zip(&additions)
+            .map(|(&(old, old_comment), &(new, new_comment))| {
+                pair_reason(content(old), content(new), old_comment, new_comment)
+            })
+            .collect();
+        if let Some(pair_reasons) = pair_reasons {
+            for ((&(old, _), &(new, _)), reason) in deletions.iter().zip(&additions).zip(pair_reasons) {
+                line_reasons[old] = Some(reason);
+                line_reasons[new] = Some(reason);
             }
-            // This is a real change
-            return false;
+            return Cosmetic::from_lines(line_reasons);
+        }
+    }
+
+    // Check if it's a formatting-only change (content reformatted across lines)
+    // This handles Tailwind class reordering, line wrapping, etc.
+    let old_lines: Vec<&str> = deletions.iter().map(|&(idx, _)| content(idx)).collect();
+    let new_lines: Vec<&str> = additions.iter().map(|&(idx, _)| content(idx)).collect();
+    if is_formatting_only_change(&old_lines, &new_lines) {
+        for &(idx, _) in additions.iter().chain(&deletions) {
+            line_reasons[idx] = Some("rewrap");
         }
-        return true;
+        return Cosmetic::from_lines(line_reasons);
     }
 
     // Different number of adds/deletes - check if all are comments
-    let all_comments = additions.iter().all(|l| is_comment_line(l))
-        && deletions.iter().all(|l| is_comment_line(l));
+    if additions.len() == deletions.len() || !all_comments {
+        return None;
+    }
+    for &(idx, _) in additions.iter().chain(&deletions) {
+        line_reasons[idx] = Some(comment_reason(content(idx)));
+    }
+    Cosmetic::from_lines(line_reasons)
+}
+
+/// Whether a hunk, or all changed lines of a file, is cosmetic and why. A syntax tree comparison
+/// decides when there is one; moved lines change the tree but still count as cosmetic with `ignore_moved`.
+fn cosmetic_change(
+    lines: &[DiffLine],
+    syntax_equal: Option<bool>,
+    comments: Option<&CommentSyntax>,
+    ignore_moved: bool,
+) -> Option<Cosmetic> {
+    match syntax_equal {
+        // The line heuristics name the reason when they can
+        Some(true) => {
+            analyze_hunk_cosmetic(lines, comments, ignore_moved).or_else(|| Cosmetic::all_lines(lines, "syntax"))
+        }
+        Some(false) => {
+            let only_moved = lines
+                .iter()
+                .filter(|l| l.line_type != "context")
+                .all(|l| l.moved.is_some());
+            if ignore_moved && only_moved {
+                Cosmetic::all_lines(lines, "moved")
+            } else {
+                None
+            }
+        }
+        None => analyze_hunk_cosmetic(lines, comments, ignore_moved),
+    }
+}
 
-    all_comments
+/// Combine the syntax tree verdicts of two changes to the same file: a change that is not
+/// cosmetic decides, otherwise both need a verdict
+fn both_syntax_equal(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    match (a, b) {
+        (Some(false), _) | (_, Some(false)) => Some(false),
+        (Some(true), Some(true)) => Some(true),
+        _ => None,
+    }
 }
 
 pub fn get_repo_info(repo_path: &str) -> Result<RepoInfo, String> {
@@ -248,7 +917,51 @@ pub fn get_repo_info(repo_path: &str) -> Result<RepoInfo, String> {
     })
 }
 
-pub fn get_changed_files(repo_path: &str, base_branch: &str) -> Result<Vec<ChangedFile>, String> {
+/// Resolve a local branch (falling back to `origin/<name>`) to its tip commit
+fn resolve_branch_commit<'r>(repo: &'r Repository, branch: &str) -> Result<Commit<'r>, String> {
+    let branch_ref = repo
+        .find_branch(branch, git2::BranchType::Local)
+        .or_else(|_| repo.find_branch(&format!("origin/{}", branch), git2::BranchType::Remote))
+        .map_err(|e| format!("Could not find branch '{}': {}", branch, e.message()))?;
+
+    branch_ref
+        .get()
+        .peel_to_commit()
+        .map_err(|e| e.message().to_string())
+}
+
+/// Resolve any revspec git understands (branch, tag, SHA, `HEAD~5`, `main@{1}`, ...) to a commit
+fn resolve_revision<'r>(repo: &'r Repository, rev: &str) -> Result<Commit<'r>, String> {
+    repo.revparse_single(rev)
+        .and_then(|obj| obj.peel_to_commit())
+        .map_err(|e| format!("Could not resolve revision '{}': {}", rev, e.message()))
+}
+
+/// The commit the diff starts from: the merge base in three-dot mode, the base tip in two-dot mode
+fn comparison_base<'r>(
+    repo: &'r Repository,
+    base: Commit<'r>,
+    head: &Commit,
+    mode: CompareMode,
+) -> Result<Commit<'r>, String> {
+    match mode {
+        CompareMode::TwoDot => Ok(base),
+        CompareMode::ThreeDot | CompareMode::FirstParent => {
+            let merge_base_oid = repo
+                .merge_base(head.id(), base.id())
+                .map_err(|e| e.message().to_string())?;
+
+            repo.find_commit(merge_base_oid)
+                .map_err(|e| e.message().to_string())
+        }
+    }
+}
+
+pub fn get_changed_files(
+    repo_path: &str,
+    base_branch: &str,
+    options: &CompareOptions,
+) -> Result<ChangeSet, String> {
     let repo = Repository::discover(repo_path).map_err(|e| e.message().to_string())?;
 
     let head_commit = repo
@@ -257,133 +970,338 @@ pub fn get_changed_files(repo_path: &str, base_branch: &str) -> Result<Vec<Chang
         .peel_to_commit()
         .map_err(|e| e.message().to_string())?;
 
-    // Find merge base between current HEAD and base branch
-    let base_ref = repo
-        .find_branch(base_branch, git2::BranchType::Local)
-        .or_else(|_| repo.find_branch(&format!("origin/{}", base_branch), git2::BranchType::Remote))
-        .map_err(|e| format!("Could not find branch '{}': {}", base_branch, e.message()))?;
+    let base_commit = resolve_branch_commit(&repo, base_branch)?;
+    let base_commit = comparison_base(&repo, base_commit, &head_commit, options.mode)?;
 
-    let base_commit = base_ref
-        .get()
-        .peel_to_commit()
-        .map_err(|e| e.message().to_string())?;
+    collect_changed_files(&repo, Some(&base_commit), &head_commit, options, true)
+}
 
-    let merge_base_oid = repo
-        .merge_base(head_commit.id(), base_commit.id())
-        .map_err(|e| e.message().to_string())?;
+/// Changed files between two arbitrary revisions. Neither side has to be checked out,
+/// so the working directory is never consulted.
+pub fn get_changed_files_between(
+    repo_path: &str,
+    base_rev: &str,
+    head_rev: &str,
+    options: &CompareOptions,
+) -> Result<ChangeSet, String> {
+    let repo = Repository::discover(repo_path).map_err(|e| e.message().to_string())?;
 
-    let merge_base_commit = repo
-        .find_commit(merge_base_oid)
-        .map_err(|e| e.message().to_string())?;
+    let head_commit = resolve_revision(&repo, head_rev)?;
+    let base_commit = resolve_revision(&repo, base_rev)?;
+    let base_commit = comparison_base(&repo, base_commit, &head_commit, options.mode)?;
 
-    let merge_base_tree = merge_base_commit
-        .tree()
-        .map_err(|e| e.message().to_string())?;
+    collect_changed_files(&repo, Some(&base_commit), &head_commit, options, false)
+}
 
-    let head_tree = head_commit.tree().map_err(|e| e.message().to_string())?;
+/// Per-file line counts and hunk lines, accumulated over one or more diffs
+#[derive(Default)]
+struct DiffTally {
+    files: HashMap<String, ChangedFile>,
+    lines: HashMap<String, Vec<DiffLine>>,
+    rename_ids: HashMap<String, (Oid, Oid)>,
+    /// Files whose content differs in at least one diff, as opposed to only their mode
+    content_changed: HashSet<String>,
+    /// Comment syntax of the files in a language that has one
+    comments: HashMap<String, &'static CommentSyntax>,
+    /// Syntax tree verdict of each file over all diffs, see `FilePatch::syntax_equal`
+    syntax_equal: HashMap<String, Option<bool>>,
+}
 
-    let mut diff_opts = DiffOptions::new();
-    diff_opts.include_untracked(true);
+impl DiffTally {
+    /// Register the file of a delta. A file already seen in an earlier diff keeps its status.
+    fn add_delta(&mut self, delta: &DiffDelta, patch: &FilePatch) {
+        let path = delta_path(delta);
+        let status = delta_to_status(delta.status());
+        let old_path = delta_old_path(delta);
 
-    // Get diff from merge base to HEAD (committed changes)
-    let diff = repo
-        .diff_tree_to_tree(Some(&merge_base_tree), Some(&head_tree), Some(&mut diff_opts))
-        .map_err(|e| e.message().to_string())?;
+        if old_path.is_some() {
+            self.rename_ids
+                .insert(path.clone(), (delta.old_file().id(), delta.new_file().id()));
+        }
 
-    // Store file info and their diff lines for cosmetic analysis
-    let files: RefCell<HashMap<String, ChangedFile>> = RefCell::new(HashMap::new());
-    let file_lines: RefCell<HashMap<String, Vec<(char, String)>>> = RefCell::new(HashMap::new());
+        let file = self.files.entry(path.clone()).or_insert_with(|| ChangedFile {
+            path: path.clone(),
+            status: status.to_string(),
+            additions: 0,
+            deletions: 0,
+            is_cosmetic: false,
+            cosmetic_reason: None,
+            old_path,
+            similarity: None,
+            layers: Vec::new(),
+            submodule: None,
+            old_mode: mode_string(delta.old_file().mode()),
+            new_mode: None,
+            old_encoding: patch.old_encoding.clone(),
+            new_encoding: None,
+            old_line_ending: patch.old_line_ending.clone(),
+            new_line_ending: None,
+            line_endings_changed: false,
+            old_lfs: patch.old_lfs.clone(),
+            new_lfs: None,
+            classification: String::new(),
+        });
+
+        // The first diff tells where the file started, later layers where it ended up
+        file.new_mode = mode_string(delta.new_file().mode());
+        file.new_encoding.clone_from(&patch.new_encoding);
+        file.new_line_ending.clone_from(&patch.new_line_ending);
+        file.new_lfs.clone_from(&patch.new_lfs);
+        if delta.old_file().id() != delta.new_file().id() {
+            self.content_changed.insert(path.clone());
+        }
+        if let Some(change) = delta_submodule(delta) {
+            let old_oid = file.submodule.take().map_or(change.old_oid, |first| first.old_oid);
+            file.submodule = Some(SubmoduleChange {
+                old_oid,
+                new_oid: change.new_oid,
+            });
+        }
+        if let Some(syntax) = patch.comments {
+            self.comments.insert(path.clone(), syntax);
+        }
+        self.syntax_equal
+            .entry(path.clone())
+            .and_modify(|equal| *equal = both_syntax_equal(*equal, patch.syntax_equal))
+            .or_insert(patch.syntax_equal);
+        self.lines.entry(path).or_default();
+    }
 
-    diff.foreach(
-        &mut |delta, _| {
-            let path = delta
-                .new_file()
-                .path()
-                .or_else(|| delta.old_file().path())
-                .map(|p| p.to_string_lossy().to_string())
-                .unwrap_or_default();
+    /// Keep a hunk line of a file, counting it if it was added or deleted
+    fn add_line(&mut self, path: &str, line: DiffLine) {
+        if let Some(file) = self.files.get_mut(path) {
+            match line.line_type.as_str() {
+                "add" => file.additions += 1,
+                "delete" => file.deletions += 1,
+                _ => {}
+            }
+        }
 
-            let status = delta_to_status(delta.status());
+        if let Some(lines) = self.lines.get_mut(path) {
+            lines.push(line);
+        }
+    }
+}
 
-            files.borrow_mut().insert(
-                path.clone(),
-                ChangedFile {
-                    path: path.clone(),
-                    status: status.to_string(),
-                    additions: 0,
-                    deletions: 0,
-                    is_cosmetic: false,
-                },
-            );
-            file_lines.borrow_mut().insert(path, Vec::new());
-            true
-        },
-        None,
-        None,
-        Some(&mut |delta, _hunk, line| {
-            let path = delta
-                .new_file()
-                .path()
-                .or_else(|| delta.old_file().path())
-                .map(|p| p.to_string_lossy().to_string())
-                .unwrap_or_default();
-
-            let origin = line.origin();
-            let content = String::from_utf8_lossy(line.content()).to_string();
-
-            if let Some(file) = files.borrow_mut().get_mut(&path) {
-                match origin {
-                    '+' => file.additions += 1,
-                    '-' => file.deletions += 1,
-                    _ => {}
-                }
-            }
+/// Add the files and line counts of a diff to the tally
+fn tally_diff(
+    repo: &Repository,
+    diff: &git2::Diff,
+    tally: &mut DiffTally,
+    options: &CompareOptions,
+) -> Result<(), String> {
+    for (idx, delta) in diff.deltas().enumerate() {
+        // Only the hunk lines are kept, so the layer they are tagged with does not matter
+        let patch = delta_patch(repo, diff, idx, "committed", options, false)?;
+        tally.add_delta(&delta, &patch);
+
+        let path = delta_path(&delta);
+        // Context lines stay for the comment analysis, which follows block comments through them
+        for line in patch.hunks.into_iter().flat_map(|hunk| hunk.lines) {
+            tally.add_line(&path, line);
+        }
+    }
+    Ok(())
+}
 
-            if origin == '+' || origin == '-' {
-                if let Some(lines) = file_lines.borrow_mut().get_mut(&path) {
-                    lines.push((origin, content));
-                }
-            }
-            true
-        }),
-    )
-    .map_err(|e| e.message().to_string())?;
+/// Tally one diff of the file list: the committed changes, a selected commit or an uncommitted layer.
+/// Moves are only needed to discount them from the cosmetic analysis, and are matched among the lines
+/// of this diff alone, since line numbers from different trees do not line up.
+fn tally_layer(
+    repo: &Repository,
+    diff: &git2::Diff,
+    tally: &mut DiffTally,
+    options: &CompareOptions,
+) -> Result<(), String> {
+    let tallied: HashMap<String, usize> = tally.lines.iter().map(|(path, lines)| (path.clone(), lines.len())).collect();
+    tally_diff(repo, diff, tally, options)?;
+    if !options.detect_moves || !options.moves_are_cosmetic {
+        return Ok(());
+    }
 
-    let mut files = files.into_inner();
-    let file_lines = file_lines.into_inner();
+    // Sorted, so that the same counterparts are picked on every call
+    let mut files: Vec<(&str, Vec<&mut DiffLine>)> = tally
+        .lines
+        .iter_mut()
+        .map(|(path, lines)| {
+            let start = tallied.get(path).copied().unwrap_or(0);
+            (path.as_str(), lines[start..].iter_mut().collect::<Vec<_>>())
+        })
+        .filter(|(_, lines)| !lines.is_empty())
+        .collect();
+    files.sort_by(|a, b| a.0.cmp(b.0));
+    moved::mark_moves(&mut files, line_compare(options));
+    Ok(())
+}
 
-    // Analyze each file for cosmetic changes
-    for (path, lines) in file_lines.iter() {
-        if let Some(file) = files.get_mut(path) {
-            // Convert to DiffLine format for analysis
-            let diff_lines: Vec<DiffLine> = lines
-                .iter()
-                .map(|(origin, content)| DiffLine {
-                    content: content.clone(),
-                    line_type: if *origin == '+' { "add".to_string() } else { "delete".to_string() },
-                    old_lineno: None,
-                    new_lineno: None,
-                })
-                .collect();
+fn collect_changed_files(
+    repo: &Repository,
+    base_commit: Option<&Commit>,
+    head_commit: &Commit,
+    options: &CompareOptions,
+    include_workdir: bool,
+) -> Result<ChangeSet, String> {
+    let DiffScope {
+        selection,
+        trees,
+        include_workdir,
+        combined,
+    } = diff_scope(repo, base_commit, head_commit, options, include_workdir)?;
 
-            file.is_cosmetic = !diff_lines.is_empty() && analyze_hunk_cosmetic(&diff_lines);
+    // Store file info and their diff lines for cosmetic analysis
+    let mut tally = DiffTally::default();
+
+    if let Some(commits) = selection {
+        for commit in commits {
+            let mut diff = commit_diff(repo, &commit, &mut diff_options(repo, options))
+                .map_err(|e| e.message().to_string())?;
+            find_similar(&mut diff, options)?;
+            tally_layer(repo, &diff, &mut tally, options)?;
         }
+    } else {
+        let mut diff_opts = diff_options(repo, options);
+        diff_opts.include_untracked(true);
+
+        // Get diff from base to head (committed changes), or from base to the working tree in the combined view
+        let mut diff = if combined {
+            diff_opts.recurse_untracked_dirs(true);
+            diff_opts.show_untracked_content(true);
+            repo.diff_tree_to_workdir_with_index(Some(&trees.base), Some(&mut diff_opts))
+        } else {
+            repo.diff_tree_to_tree(Some(&trees.base), Some(&trees.branch), Some(&mut diff_opts))
+        }
+        .map_err(|e| e.message().to_string())?;
+
+        find_similar(&mut diff, options)?;
+        tally_layer(repo, &diff, &mut tally, options)?;
     }
 
-    // Also check working directory for uncommitted changes
-    let mut status_opts = StatusOptions::new();
-    status_opts.include_untracked(true);
-    status_opts.recurse_untracked_dirs(true);
+    // Tag the files that have committed changes
+    if combined {
+        let committed = repo
+            .diff_tree_to_tree(Some(&trees.base), Some(&trees.head), None)
+            .map_err(|e| e.message().to_string())?;
+
+        for delta in committed.deltas() {
+            if let Some(file) = tally.files.get_mut(&delta_path(&delta)) {
+                file.layers.push("committed".to_string());
+            }
+        }
+    } else {
+        for file in tally.files.values_mut() {
+            file.layers.push("committed".to_string());
+        }
+    }
+
+    // Count the uncommitted layers with the same diffs get_file_diff stacks on top of the committed one
+    if include_workdir && !combined {
+        let index = repo.index().map_err(|e| e.message().to_string())?;
+
+        let mut staged_diff = repo
+            .diff_tree_to_index(Some(&trees.head), Some(&index), Some(&mut diff_options(repo, options)))
+            .map_err(|e| e.message().to_string())?;
+        find_similar(&mut staged_diff, options)?;
+        tally_layer(repo, &staged_diff, &mut tally, options)?;
+
+        let mut unstaged_opts = diff_options(repo, options);
+        unstaged_opts.include_untracked(true);
+        unstaged_opts.recurse_untracked_dirs(true);
+        unstaged_opts.show_untracked_content(true);
+
+        let unstaged_diff = repo
+            .diff_index_to_workdir(Some(&index), Some(&mut unstaged_opts))
+            .map_err(|e| e.message().to_string())?;
+        tally_layer(repo, &unstaged_diff, &mut tally, options)?;
+    }
+
+    let DiffTally {
+        mut files,
+        lines: file_lines,
+        rename_ids,
+        content_changed,
+        comments,
+        syntax_equal,
+    } = tally;
+
+    for file in files.values_mut() {
+        if file.status == "modified" && file.old_mode != file.new_mode && !content_changed.contains(&file.path) {
+            file.status = "mode-changed".to_string();
+        }
+        file.line_endings_changed = line_endings_differ(&file.old_line_ending, &file.new_line_ending);
+    }
+
+    for (path, (old_id, new_id)) in rename_ids {
+        if let Some(file) = files.get_mut(&path) {
+            file.similarity = rename_similarity(repo, old_id, new_id);
+        }
+    }
+
+    // Analyze each file for cosmetic changes
+    for (path, lines) in file_lines.iter() {
+        if let Some(file) = files.get_mut(path) {
+            // With normalized line endings a pure conversion leaves no lines behind
+            file.cosmetic_reason = if lines.is_empty() {
+                file.line_endings_changed.then(|| "line-ending".to_string())
+            } else {
+                cosmetic_change(
+                    lines,
+                    syntax_equal.get(path).copied().flatten(),
+                    comments.get(path).copied(),
+                    options.moves_are_cosmetic,
+                )
+                .map(|cosmetic| cosmetic.reason.to_string())
+            };
+            file.is_cosmetic = file.cosmetic_reason.is_some();
+        }
+    }
+
+    if include_workdir {
+        add_workdir_files(repo, &mut files)?;
+    }
+
+    for file in files.values_mut() {
+        file.classification = classify::classify(repo, &file.path).to_string();
+    }
+
+    let mut result: Vec<ChangedFile> = files.into_values().collect();
+    result.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(ChangeSet {
+        files: result,
+        mode: options.mode,
+        base_oid: base_commit.map_or_else(Oid::zero, Commit::id).to_string(),
+        head_oid: head_commit.id().to_string(),
+    })
+}
+
+/// Add files with uncommitted changes that the tree diff did not already report,
+/// and tag every file with the uncommitted layers it has changes in
+fn add_workdir_files(repo: &Repository, files: &mut HashMap<String, ChangedFile>) -> Result<(), String> {
+    // Also check working directory for uncommitted changes
+    let mut status_opts = StatusOptions::new();
+    status_opts.include_untracked(true);
+    status_opts.recurse_untracked_dirs(true);
 
     let statuses = repo
         .statuses(Some(&mut status_opts))
         .map_err(|e| e.message().to_string())?;
 
+    // The status scan has no rename detection, so the source of a rename the diffs paired up shows up
+    // on its own as deleted
+    let renamed_from: HashSet<String> = files
+        .values()
+        .filter(|file| file.status == "renamed")
+        .filter_map(|file| file.old_path.clone())
+        .collect();
+
     for entry in statuses.iter() {
         let path = entry.path().unwrap_or("").to_string();
         let status = entry.status();
 
         if !files.contains_key(&path) {
+            if renamed_from.contains(&path) {
+                continue;
+            }
             let status_str = if status.is_wt_new() || status.is_index_new() {
                 "added"
             } else if status.is_wt_deleted() || status.is_index_deleted() {
@@ -397,22 +1315,60 @@ pub fn get_changed_files(repo_path: &str, base_branch: &str) -> Result<Vec<Chang
             files.insert(
                 path.clone(),
                 ChangedFile {
-                    path,
+                    path: path.clone(),
                     status: status_str.to_string(),
                     additions: 0,
                     deletions: 0,
                     is_cosmetic: false,
+                    cosmetic_reason: None,
+                    old_path: None,
+                    similarity: None,
+                    layers: Vec::new(),
+                    submodule: None,
+                    old_mode: None,
+                    new_mode: None,
+                    old_encoding: None,
+                    new_encoding: None,
+                    old_line_ending: None,
+                    new_line_ending: None,
+                    line_endings_changed: false,
+                    old_lfs: None,
+                    new_lfs: None,
+                    classification: String::new(),
                 },
             );
         }
+
+        let Some(file) = files.get_mut(&path) else {
+            continue;
+        };
+
+        if status.is_index_new()
+            || status.is_index_modified()
+            || status.is_index_deleted()
+            || status.is_index_renamed()
+            || status.is_index_typechange()
+        {
+            file.layers.push("staged".to_string());
+        }
+        if status.is_wt_modified() || status.is_wt_deleted() || status.is_wt_renamed() || status.is_wt_typechange() {
+            file.layers.push("unstaged".to_string());
+        }
+        if status.is_wt_new() {
+            file.layers.push("untracked".to_string());
+        }
     }
 
-    let mut result: Vec<ChangedFile> = files.into_values().collect();
-    result.sort_by(|a, b| a.path.cmp(&b.path));
-    Ok(result)
+    Ok(())
 }
 
-pub fn get_file_diff(repo_path: &str, file_path: &str, base_branch: &str) -> Result<FileDiff, String> {
+pub fn get_file_diff(
+    repo_path: &str,
+    file_path: &str,
+    base_branch: &str,
+    window: Option<DiffWindow>,
+    options: &CompareOptions,
+) -> Result<FileDiff, String> {
     let repo = Repository::discover(repo_path).map_err(|e| e.message().to_string())?;
 
     let head_commit = repo
@@ -421,158 +1377,1235 @@ pub fn get_file_diff(repo_path: &str, file_path: &str, base_branch: &str) -> Res
         .peel_to_commit()
         .map_err(|e| e.message().to_string())?;
 
-    let base_ref = repo
-        .find_branch(base_branch, git2::BranchType::Local)
-        .or_else(|_| repo.find_branch(&format!("origin/{}", base_branch), git2::BranchType::Remote))
-        .map_err(|e| format!("Could not find branch '{}': {}", base_branch, e.message()))?;
+    let base_commit = resolve_branch_commit(&repo, base_branch)?;
+    let base_commit = comparison_base(&repo, base_commit, &head_commit, options.mode)?;
 
-    let base_commit = base_ref
-        .get()
-        .peel_to_commit()
-        .map_err(|e| e.message().to_string())?;
+    collect_file_diff(&repo, file_path, Some(&base_commit), &head_commit, window, options, true)
+}
 
-    let merge_base_oid = repo
-        .merge_base(head_commit.id(), base_commit.id())
-        .map_err(|e| e.message().to_string())?;
+/// Diff of a single file between two arbitrary revisions
+pub fn get_file_diff_between(
+    repo_path: &str,
+    file_path: &str,
+    base_rev: &str,
+    head_rev: &str,
+    window: Option<DiffWindow>,
+    options: &CompareOptions,
+) -> Result<FileDiff, String> {
+    let repo = Repository::discover(repo_path).map_err(|e| e.message().to_string())?;
 
-    let merge_base_commit = repo
-        .find_commit(merge_base_oid)
-        .map_err(|e| e.message().to_string())?;
+    let head_commit = resolve_revision(&repo, head_rev)?;
+    let base_commit = resolve_revision(&repo, base_rev)?;
+    let base_commit = comparison_base(&repo, base_commit, &head_commit, options.mode)?;
 
-    let merge_base_tree = merge_base_commit
-        .tree()
-        .map_err(|e| e.message().to_string())?;
+    collect_file_diff(&repo, file_path, Some(&base_commit), &head_commit, window, options, false)
+}
 
-    let head_tree = head_commit.tree().map_err(|e| e.message().to_string())?;
+/// Hunks of a single file, extracted from a diff
+#[derive(Default)]
+struct FilePatch {
+    hunks: Vec<DiffHunk>,
+    is_binary: bool,
+    old_path: Option<String>,
+    old_mode: Option<String>,
+    new_mode: Option<String>,
+    old_symlink_target: Option<String>,
+    new_symlink_target: Option<String>,
+    old_encoding: Option<String>,
+    new_encoding: Option<String>,
+    old_line_ending: Option<String>,
+    new_line_ending: Option<String>,
+    old_lfs: Option<LfsInfo>,
+    new_lfs: Option<LfsInfo>,
+    comments: Option<&'static CommentSyntax>,
+    /// Whether the syntax trees of the two sides are equal apart from comments and whitespace,
+    /// `None` when not compared. Only computed for tallies.
+    syntax_equal: Option<bool>,
+    /// The same verdict for each hunk on its own, only computed for file diffs
+    hunk_syntax_equal: Vec<Option<bool>>,
+}
 
-    // Helper to extract hunks from a diff
-    fn extract_hunks(diff: &git2::Diff) -> Result<(Vec<DiffHunk>, bool), String> {
-        let hunks: RefCell<Vec<DiffHunk>> = RefCell::new(Vec::new());
-        let is_binary = RefCell::new(false);
+impl FilePatch {
+    /// Stack the patch of a later diff on top of this one
+    fn stack(mut self, later: FilePatch) -> FilePatch {
+        self.hunks.extend(later.hunks);
+        self.is_binary |= later.is_binary;
+        self.old_path = self.old_path.or(later.old_path);
+        self.new_mode = later.new_mode;
+        self.new_symlink_target = later.new_symlink_target;
+        self.new_encoding = later.new_encoding;
+        self.new_line_ending = later.new_line_ending;
+        self.new_lfs = later.new_lfs;
+        self.comments = later.comments.or(self.comments);
+        self.syntax_equal = both_syntax_equal(self.syntax_equal, later.syntax_equal);
+        self.hunk_syntax_equal.extend(later.hunk_syntax_equal);
+        self
+    }
+}
 
-        diff.foreach(
-            &mut |delta, _| {
-                *is_binary.borrow_mut() = delta.new_file().is_binary() || delta.old_file().is_binary();
-                true
-            },
-            Some(&mut |_, _| true),
-            Some(&mut |_delta, hunk| {
-                hunks.borrow_mut().push(DiffHunk {
-                    old_start: hunk.old_start(),
-                    old_lines: hunk.old_lines(),
-                    new_start: hunk.new_start(),
-                    new_lines: hunk.new_lines(),
-                    lines: Vec::new(),
-                    is_cosmetic: false,
-                });
-                true
-            }),
-            Some(&mut |_delta, _hunk, line| {
-                if let Some(current_hunk) = hunks.borrow_mut().last_mut() {
-                    let line_type = match line.origin() {
-                        '+' => "add",
-                        '-' => "delete",
-                        ' ' => "context",
-                        _ => "context",
-                    };
-
-                    let content = String::from_utf8_lossy(line.content()).to_string();
-
-                    current_hunk.lines.push(DiffLine {
-                        content,
-                        line_type: line_type.to_string(),
-                        old_lineno: line.old_lineno(),
-                        new_lineno: line.new_lineno(),
-                    });
-                }
-                true
-            }),
+/// Collect the hunks of `path` from a diff, tagged with `layer`, or `None` if the diff does not touch it
+fn extract_hunks(
+    repo: &Repository,
+    diff: &git2::Diff,
+    path: &str,
+    layer: &str,
+    options: &CompareOptions,
+) -> Result<Option<FilePatch>, String> {
+    let Some(idx) = diff.deltas().position(|delta| delta_path(&delta) == path) else {
+        return Ok(None);
+    };
+
+    delta_patch(repo, diff, idx, layer, options, true).map(Some)
+}
+
+/// Hunks of the delta at `idx` of a diff, tagged with `layer`. For a file diff (`file_diff`) the lines
+/// get syntax tokens and each hunk its own syntax tree verdict, otherwise the file gets one verdict.
+fn delta_patch(
+    repo: &Repository,
+    diff: &git2::Diff,
+    idx: usize,
+    layer: &str,
+    options: &CompareOptions,
+    file_diff: bool,
+) -> Result<FilePatch, String> {
+    let delta = diff.get_delta(idx).ok_or("Diff delta disappeared")?;
+    let contents = delta_contents(repo, &delta);
+    let text = delta_text(&contents, options);
+    let converted = contents.is_converted() || text.as_ref().is_some_and(DeltaText::is_converted);
+
+    // Diff the decoded text instead of the raw bytes when the two differ. The same goes for symlinks,
+    // whose text is the link target, or nothing for the link side of a file replaced by a link.
+    let involves_link = delta.old_file().mode() == FileMode::Link || delta.new_file().mode() == FileMode::Link;
+    let patch = match &text {
+        Some(text) if converted || involves_link => Patch::from_buffers(
+            text.old_text().as_bytes(),
+            None,
+            text.new_text().as_bytes(),
+            None,
+            Some(&mut diff_options(repo, options)),
         )
-        .map_err(|e| e.message().to_string())?;
+        .map(Some),
+        // Binary by gitattributes, or binary LFS content that a diff of the pointers would not describe
+        None if converted || contents.is_binary => Ok(None),
+        _ => Patch::from_diff(diff, idx),
+    }
+    .map_err(|e| e.message().to_string())?;
+    // Untracked files come out of the index -> working tree diff
+    let layer = if layer == "unstaged" && delta.status() == Delta::Untracked {
+        "untracked"
+    } else {
+        layer
+    };
 
-        Ok((hunks.into_inner(), is_binary.into_inner()))
+    let old_side = text.as_ref().and_then(|text| text.old.as_ref());
+    let new_side = text.as_ref().and_then(|text| text.new.as_ref());
+    let mut file_patch = FilePatch {
+        hunks: Vec::new(),
+        // libgit2 judges the raw bytes, which take UTF-16 for binary and know nothing of LFS objects
+        // or textconv
+        is_binary: if converted || contents.is_binary {
+            text.is_none()
+        } else {
+            delta.new_file().is_binary() || delta.old_file().is_binary()
+        },
+        old_path: delta_old_path(&delta),
+        old_mode: mode_string(delta.old_file().mode()),
+        new_mode: mode_string(delta.new_file().mode()),
+        old_symlink_target: symlink_target(repo, &delta.old_file()),
+        new_symlink_target: symlink_target(repo, &delta.new_file()),
+        old_encoding: old_side.map(|side| side.encoding.to_string()),
+        new_encoding: new_side.map(|side| side.encoding.to_string()),
+        old_line_ending: old_side.and_then(|side| side.line_ending).map(str::to_string),
+        new_line_ending: new_side.and_then(|side| side.line_ending).map(str::to_string),
+        old_lfs: contents.old_lfs,
+        new_lfs: contents.new_lfs,
+        // Scripts without an extension are recognized by their shebang
+        comments: comments::syntax_for(
+            &delta_path(&delta),
+            text.as_ref().and_then(|text| text.new_text().lines().next().or(text.old_text().lines().next())),
+        ),
+        syntax_equal: None,
+        hunk_syntax_equal: Vec::new(),
+    };
+
+    if effective_algorithm(repo, options) == DiffAlgorithm::Histogram {
+        match &text {
+            Some(text) => {
+                let old_lines: Vec<&str> = text.old_text().split_inclusive('\n').collect();
+                let new_lines: Vec<&str> = text.new_text().split_inclusive('\n').collect();
+                let compare = line_compare(options);
+                let script = histogram::diff_lines(&old_lines, &new_lines, compare);
+                file_patch.hunks = histogram::build_hunks(
+                    &old_lines,
+                    &new_lines,
+                    &script,
+                    compare,
+                    options.context_lines as usize,
+                    layer,
+                );
+            }
+            None => file_patch.is_binary = true,
+        }
+    } else if let Some(patch) = patch {
+        // Binary and unchanged files have no patch
+        file_patch.is_binary |= patch.delta().new_file().is_binary() || patch.delta().old_file().is_binary();
+        file_patch.hunks = patch_hunks(&patch, layer)?;
     }
 
-    let mut diff_opts = DiffOptions::new();
-    diff_opts.pathspec(file_path);
-    diff_opts.context_lines(3);
+    let syntax_text = text.as_ref().filter(|_| options.syntax_aware_cosmetic);
+    if file_diff {
+        file_patch.hunk_syntax_equal = match syntax_text {
+            Some(text) => {
+                syntax_tree::hunks_equivalent(&delta_path(&delta), text.old_text(), text.new_text(), &file_patch.hunks)
+            }
+            None => vec![None; file_patch.hunks.len()],
+        };
+    } else if let Some(text) = syntax_text {
+        file_patch.syntax_equal = syntax_tree::file_equivalent(&delta_path(&delta), text.old_text(), text.new_text());
+    }
 
-    // First try: diff from merge base to HEAD (committed changes)
-    let committed_diff = repo
-        .diff_tree_to_tree(Some(&merge_base_tree), Some(&head_tree), Some(&mut diff_opts))
-        .map_err(|e| e.message().to_string())?;
+    if file_diff && options.syntax_highlight {
+        if let Some(text) = &text {
+            let old_path = delta.old_file().path().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+            highlight_hunks(&old_path, &delta_path(&delta), text, &mut file_patch.hunks);
+        }
+    }
 
-    let (mut hunks, mut is_binary) = extract_hunks(&committed_diff)?;
+    Ok(file_patch)
+}
 
-    // If no committed changes found, try working directory changes (uncommitted)
-    if hunks.is_empty() {
-        let mut diff_opts_workdir = DiffOptions::new();
-        diff_opts_workdir.pathspec(file_path);
-        diff_opts_workdir.context_lines(3);
-        diff_opts_workdir.include_untracked(true);
+/// Hunks of a libgit2 patch, tagged with `layer`
+fn patch_hunks(patch: &Patch, layer: &str) -> Result<Vec<DiffHunk>, String> {
+    let mut hunks = Vec::with_capacity(patch.num_hunks());
+    for hunk_idx in 0..patch.num_hunks() {
+        let (hunk, line_count) = patch.hunk(hunk_idx).map_err(|e| e.message().to_string())?;
+        let mut lines = Vec::with_capacity(line_count);
+
+        for line_idx in 0..line_count {
+            let line = patch
+                .line_in_hunk(hunk_idx, line_idx)
+                .map_err(|e| e.message().to_string())?;
+
+            let line_type = match line.origin() {
+                '+' => "add",
+                '-' => "delete",
+                ' ' => "context",
+                _ => "context",
+            };
 
-        let workdir_diff = repo
-            .diff_tree_to_workdir_with_index(Some(&merge_base_tree), Some(&mut diff_opts_workdir))
-            .map_err(|e| e.message().to_string())?;
+            lines.push(DiffLine {
+                content: String::from_utf8_lossy(line.content()).to_string(),
+                line_type: line_type.to_string(),
+                old_lineno: line.old_lineno(),
+                new_lineno: line.new_lineno(),
+                highlights: Vec::new(),
+                moved: None,
+                tokens: Vec::new(),
+                cosmetic_reason: None,
+            });
+        }
 
-        let (workdir_hunks, workdir_is_binary) = extract_hunks(&workdir_diff)?;
-        hunks = workdir_hunks;
-        is_binary = workdir_is_binary;
+        hunks.push(DiffHunk {
+            old_start: hunk.old_start(),
+            old_lines: hunk.old_lines(),
+            new_start: hunk.new_start(),
+            new_lines: hunk.new_lines(),
+            lines,
+            is_cosmetic: false,
+            cosmetic_reason: None,
+            line_endings_changed: false,
+            layer: layer.to_string(),
+            line_offset: 0,
+            commit: None,
+        });
     }
 
-    // If still no hunks, file might be untracked - read it directly
-    if hunks.is_empty() {
-        let workdir = repo.workdir().ok_or("No working directory")?;
-        let full_path = workdir.join(file_path);
+    Ok(hunks)
+}
 
-        if full_path.exists() {
-            // Check if it's a binary file
-            let content = std::fs::read(&full_path).map_err(|e| e.to_string())?;
-            let is_binary_file = content.iter().take(8000).any(|&b| b == 0);
+/// Attach syntax tokens to the lines of hunks. Each side is parsed whole, up to its last line in the
+/// hunks; deleted lines take their tokens from the old side, added lines from the new, and context
+/// lines from whichever side holds the text shown (they can differ when whitespace is ignored).
+fn highlight_hunks(old_path: &str, new_path: &str, text: &DeltaText, hunks: &mut [DiffHunk]) {
+    let lines = hunks.iter().flat_map(|hunk| &hunk.lines);
+    let old_count = lines.clone().filter_map(|line| line.old_lineno).max().unwrap_or(0) as usize;
+    let new_count = lines.filter_map(|line| line.new_lineno).max().unwrap_or(0) as usize;
+    let old_tokens = syntax::highlight(old_path, text.old_text(), old_count);
+    let new_tokens = syntax::highlight(new_path, text.new_text(), new_count);
+    if old_tokens.is_none() && new_tokens.is_none() {
+        return;
+    }
+    let old_lines: Vec<&str> = text.old_text().split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = text.new_text().split_inclusive('\n').collect();
 
-            if is_binary_file {
-                is_binary = true;
-            } else {
-                // Create synthetic diff showing all lines as additions
-                let text = String::from_utf8_lossy(&content);
-                let lines: Vec<DiffLine> = text
-                    .lines()
-                    .enumerate()
-                    .map(|(i, line)| DiffLine {
-                        content: format!("{}\n", line),
-                        line_type: "add".to_string(),
-                        old_lineno: None,
-                        new_lineno: Some((i + 1) as u32),
-                    })
-                    .collect();
-
-                if !lines.is_empty() {
-                    hunks.push(DiffHunk {
-                        old_start: 0,
-                        old_lines: 0,
-                        new_start: 1,
-                        new_lines: lines.len() as u32,
-                        lines,
-                        is_cosmetic: false,
-                    });
-                }
-            }
+    let side_tokens = |tokens: &Option<Vec<Vec<SyntaxToken>>>, lines: &[&str], lineno: Option<u32>, content: &str| {
+        let idx = lineno? as usize - 1;
+        if lines.get(idx) != Some(&content) {
+            return None;
+        }
+        tokens.as_ref()?.get(idx).cloned()
+    };
+
+    for line in hunks.iter_mut().flat_map(|hunk| hunk.lines.iter_mut()) {
+        let old = || side_tokens(&old_tokens, &old_lines, line.old_lineno, &line.content);
+        let new = || side_tokens(&new_tokens, &new_lines, line.new_lineno, &line.content);
+        let tokens = match line.line_type.as_str() {
+            "delete" => old(),
+            "add" => new(),
+            _ => new().or_else(old),
+        };
+        line.tokens = tokens.unwrap_or_default();
+    }
+}
+
+/// Diff restricted to `file_path`. A file that shows up as added may be the target of a rename
+/// or copy; the pathspec hides its source, so in that case the diff is redone over all paths
+/// and rename detection pairs it up.
+fn path_diff<'r>(
+    repo: &Repository,
+    file_path: &str,
+    options: &CompareOptions,
+    make_diff: impl Fn(&mut DiffOptions) -> Result<git2::Diff<'r>, git2::Error>,
+) -> Result<git2::Diff<'r>, String> {
+    let mut diff_opts = diff_options(repo, options);
+    diff_opts.pathspec(file_path);
+
+    let diff = make_diff(&mut diff_opts).map_err(|e| e.message().to_string())?;
+
+    let is_added = diff
+        .deltas()
+        .any(|delta| matches!(delta.status(), Delta::Added | Delta::Untracked));
+    if !options.detect_renames || !is_added {
+        return Ok(diff);
+    }
+
+    let mut full_opts = diff_options(repo, options);
+
+    let mut full_diff = make_diff(&mut full_opts).map_err(|e| e.message().to_string())?;
+    find_similar(&mut full_diff, options)?;
+    Ok(full_diff)
+}
+
+/// Hunks of `file_path` introduced by each of `commits`, in order, or `None` if none of them touch it
+fn commit_hunks(
+    repo: &Repository,
+    file_path: &str,
+    commits: &[Commit],
+    options: &CompareOptions,
+) -> Result<Option<FilePatch>, String> {
+    let mut patches = Vec::new();
+
+    for commit in commits {
+        let diff = path_diff(repo, file_path, options, |opts| commit_diff(repo, commit, opts))?;
+        let Some(mut patch) = extract_hunks(repo, &diff, file_path, "committed", options)? else {
+            continue;
+        };
+
+        for hunk in patch.hunks.iter_mut() {
+            hunk.commit = Some(commit.id().to_string());
         }
+        patches.push(patch);
+    }
+
+    Ok(patches.into_iter().reduce(FilePatch::stack))
+}
+
+fn collect_file_diff(
+    repo: &Repository,
+    file_path: &str,
+    base_commit: Option<&Commit>,
+    head_commit: &Commit,
+    window: Option<DiffWindow>,
+    options: &CompareOptions,
+    include_workdir: bool,
+) -> Result<FileDiff, String> {
+    let DiffScope {
+        selection,
+        trees,
+        include_workdir,
+        combined,
+    } = diff_scope(repo, base_commit, head_commit, options, include_workdir)?;
+
+    let mut file_patch = if let Some(commits) = selection {
+        commit_hunks(repo, file_path, &commits, options)?
+    } else {
+        // Committed changes, or in the combined view everything from base to the working tree
+        let (diff, layer) = if combined {
+            let diff = path_diff(repo, file_path, options, |opts| {
+                opts.include_untracked(true)
+                    .recurse_untracked_dirs(true)
+                    .show_untracked_content(true);
+                repo.diff_tree_to_workdir_with_index(Some(&trees.base), Some(opts))
+            })?;
+            (diff, "combined")
+        } else {
+            let diff = path_diff(repo, file_path, options, |opts| {
+                repo.diff_tree_to_tree(Some(&trees.base), Some(&trees.branch), Some(opts))
+            })?;
+            (diff, "committed")
+        };
+
+        extract_hunks(repo, &diff, file_path, layer, options)?
+    };
+
+    // Stack the uncommitted layers on top: HEAD -> index, then index -> working tree
+    if include_workdir && !combined {
+        let index = repo.index().map_err(|e| e.message().to_string())?;
+
+        // Through path_diff, so that a staged rename pairs up the way the file list shows it
+        let staged_diff = path_diff(repo, file_path, options, |opts| {
+            repo.diff_tree_to_index(Some(&trees.head), Some(&index), Some(opts))
+        })?;
+        let unstaged_diff = path_diff(repo, file_path, options, |opts| {
+            opts.include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .show_untracked_content(true);
+            repo.diff_index_to_workdir(Some(&index), Some(opts))
+        })?;
+
+        let layers = [
+            extract_hunks(repo, &staged_diff, file_path, "staged", options)?,
+            extract_hunks(repo, &unstaged_diff, file_path, "unstaged", options)?,
+        ];
+        file_patch = file_patch.into_iter().chain(layers.into_iter().flatten()).reduce(FilePatch::stack);
+    }
+
+    let FilePatch {
+        mut hunks,
+        is_binary,
+        old_path,
+        old_mode,
+        new_mode,
+        old_symlink_target,
+        new_symlink_target,
+        old_encoding,
+        new_encoding,
+        old_line_ending,
+        new_line_ending,
+        old_lfs,
+        new_lfs,
+        comments,
+        hunk_syntax_equal,
+        ..
+    } = file_patch.unwrap_or_default();
+    let line_endings_changed = line_endings_differ(&old_line_ending, &new_line_ending);
+
+    if options.detect_moves {
+        mark_file_moves(repo, file_path, &mut hunks, &trees, options)?;
     }
 
     // Analyze each hunk for cosmetic changes
-    for hunk in hunks.iter_mut() {
-        hunk.is_cosmetic = analyze_hunk_cosmetic(&hunk.lines);
+    let mut cosmetic_reasons = BTreeMap::new();
+    for (hunk, syntax_equal) in hunks.iter_mut().zip(hunk_syntax_equal) {
+        if let Some(cosmetic) = cosmetic_change(&hunk.lines, syntax_equal, comments, options.moves_are_cosmetic) {
+            for (line, reason) in hunk.lines.iter_mut().zip(cosmetic.line_reasons) {
+                line.cosmetic_reason = reason.map(str::to_string);
+            }
+            *cosmetic_reasons.entry(cosmetic.reason.to_string()).or_insert(0) += 1;
+            hunk.is_cosmetic = true;
+            hunk.cosmetic_reason = Some(cosmetic.reason.to_string());
+        }
+        hunk.line_endings_changed = is_line_ending_only_change(&hunk.lines);
     }
 
-    // File is cosmetic if all hunks are cosmetic
-    let all_cosmetic = !hunks.is_empty() && hunks.iter().all(|h| h.is_cosmetic);
+    // File is cosmetic if all hunks are cosmetic, or if only its normalized line endings changed
+    let all_cosmetic = if hunks.is_empty() {
+        line_endings_changed
+    } else {
+        hunks.iter().all(|h| h.is_cosmetic)
+    };
+
+    let total_hunks = hunks.len() as u32;
+    let total_lines: usize = hunks.iter().map(|h| h.lines.len()).sum();
+
+    let (window, is_truncated) = match window {
+        Some(DiffWindow { offset, limit }) => (offset as usize..offset.saturating_add(limit) as usize, false),
+        None if options.max_diff_lines > 0 && total_lines > options.max_diff_lines as usize => (0..0, true),
+        None => (0..total_lines, false),
+    };
+
+    // Keep the hunks that overlap the window, cut down to the lines inside it
+    let mut next_start = 0;
+    hunks.retain_mut(|hunk| {
+        let start = next_start;
+        next_start += hunk.lines.len();
+
+        let (from, to) = (window.start.max(start), window.end.min(next_start));
+        if from >= to {
+            return false;
+        }
+
+        // Highlight the whole hunk so lines at the edge of the window are still paired up
+        intraline::highlight_hunk(&mut hunk.lines, options.intraline);
+        hunk.lines.truncate(to - start);
+        hunk.lines.drain(..from - start);
+        hunk.line_offset = (from - start) as u32;
+        true
+    });
 
     Ok(FileDiff {
         path: file_path.to_string(),
+        old_path,
         hunks,
         is_binary,
         is_cosmetic: all_cosmetic,
+        cosmetic_reasons,
+        mode: options.mode,
+        base_oid: base_commit.map_or_else(Oid::zero, Commit::id).to_string(),
+        total_hunks,
+        total_lines: total_lines as u32,
+        is_truncated,
+        old_mode,
+        new_mode,
+        old_symlink_target,
+        new_symlink_target,
+        old_encoding,
+        new_encoding,
+        old_line_ending,
+        new_line_ending,
+        line_endings_changed,
+        old_lfs,
+        new_lfs,
+    })
+}
+
+/// Unrestricted diff behind one layer of a file diff
+fn layer_diff<'r>(
+    repo: &'r Repository,
+    layer: &str,
+    trees: &CompareTrees,
+    options: &CompareOptions,
+) -> Result<git2::Diff<'r>, String> {
+    let mut opts = diff_options(repo, options);
+    let index = repo.index().map_err(|e| e.message().to_string())?;
+
+    let mut diff = match layer {
+        "combined" => {
+            opts.include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .show_untracked_content(true);
+            repo.diff_tree_to_workdir_with_index(Some(&trees.base), Some(&mut opts))
+        }
+        "staged" => repo.diff_tree_to_index(Some(&trees.head), Some(&index), Some(&mut opts)),
+        "unstaged" | "untracked" => {
+            opts.include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .show_untracked_content(true);
+            repo.diff_index_to_workdir(Some(&index), Some(&mut opts))
+        }
+        _ => repo.diff_tree_to_tree(Some(&trees.base), Some(&trees.branch), Some(&mut opts)),
+    }
+    .map_err(|e| e.message().to_string())?;
+
+    find_similar(&mut diff, options)?;
+    Ok(diff)
+}
+
+/// Mark the moved lines in a file's hunks. Each layer (or selected commit) is matched on its own,
+/// against the changed lines of that layer in every file of the comparison.
+fn mark_file_moves(
+    repo: &Repository,
+    file_path: &str,
+    hunks: &mut [DiffHunk],
+    trees: &CompareTrees,
+    options: &CompareOptions,
+) -> Result<(), String> {
+    // Untracked files come out of the same diff as unstaged changes
+    let source = |hunk: &DiffHunk| match hunk.layer.as_str() {
+        "untracked" => ("unstaged".to_string(), None),
+        layer => (layer.to_string(), hunk.commit.clone()),
+    };
+
+    let mut sources: Vec<(String, Option<String>)> = hunks.iter().map(source).collect();
+    sources.dedup();
+
+    for (layer, commit) in sources {
+        let diff = match &commit {
+            Some(oid) => {
+                let commit = resolve_revision(repo, oid)?;
+                let mut diff = commit_diff(repo, &commit, &mut diff_options(repo, options))
+                    .map_err(|e| e.message().to_string())?;
+                find_similar(&mut diff, options)?;
+                diff
+            }
+            None => layer_diff(repo, &layer, trees, options)?,
+        };
+        let mut tally = DiffTally::default();
+        tally_diff(repo, &diff, &mut tally, options)?;
+
+        let mut other_lines = tally.lines;
+        other_lines.remove(file_path);
+
+        let own_lines = hunks
+            .iter_mut()
+            .filter(|hunk| source(hunk) == (layer.clone(), commit.clone()))
+            .flat_map(|hunk| hunk.lines.iter_mut())
+            .collect();
+
+        // Sorted, so that the same counterparts are picked on every call
+        let mut other_files: Vec<(&str, Vec<&mut DiffLine>)> = other_lines
+            .iter_mut()
+            .map(|(path, lines)| (path.as_str(), lines.iter_mut().collect()))
+            .collect();
+        other_files.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut files: Vec<(&str, Vec<&mut DiffLine>)> = vec![(file_path, own_lines)];
+        files.extend(other_files);
+        moved::mark_moves(&mut files, line_compare(options));
+    }
+
+    Ok(())
+}
+
+pub fn get_file_lines(
+    repo_path: &str,
+    file_path: &str,
+    base_branch: &str,
+    side: DiffSide,
+    start: u32,
+    end: u32,
+    options: &CompareOptions,
+) -> Result<FileLines, String> {
+    let repo = Repository::discover(repo_path).map_err(|e| e.message().to_string())?;
+
+    let head_commit = repo
+        .head()
+        .map_err(|e| e.message().to_string())?
+        .peel_to_commit()
+        .map_err(|e| e.message().to_string())?;
+
+    let base_commit = resolve_branch_commit(&repo, base_branch)?;
+    let base_commit = comparison_base(&repo, base_commit, &head_commit, options.mode)?;
+
+    let contents = file_contents(&repo, file_path, Some(&base_commit), &head_commit, options, true)?;
+    collect_file_lines(&repo, file_path, contents, side, start..=end, options)
+}
+
+/// Line range of a file between two arbitrary revisions
+#[allow(clippy::too_many_arguments)]
+pub fn get_file_lines_between(
+    repo_path: &str,
+    file_path: &str,
+    base_rev: &str,
+    head_rev: &str,
+    side: DiffSide,
+    start: u32,
+    end: u32,
+    options: &CompareOptions,
+) -> Result<FileLines, String> {
+    let repo = Repository::discover(repo_path).map_err(|e| e.message().to_string())?;
+
+    let head_commit = resolve_revision(&repo, head_rev)?;
+    let base_commit = resolve_revision(&repo, base_rev)?;
+    let base_commit = comparison_base(&repo, base_commit, &head_commit, options.mode)?;
+
+    let contents = file_contents(&repo, file_path, Some(&base_commit), &head_commit, options, false)?;
+    collect_file_lines(&repo, file_path, contents, side, start..=end, options)
+}
+
+/// Raw old and new contents of a file, for comparing images and other binary files
+pub fn get_file_blobs(
+    repo_path: &str,
+    file_path: &str,
+    base_branch: &str,
+    max_bytes: Option<u64>,
+    options: &CompareOptions,
+) -> Result<FileBlobs, String> {
+    let repo = Repository::discover(repo_path).map_err(|e| e.message().to_string())?;
+
+    let head_commit = repo
+        .head()
+        .map_err(|e| e.message().to_string())?
+        .peel_to_commit()
+        .map_err(|e| e.message().to_string())?;
+
+    let base_commit = resolve_branch_commit(&repo, base_branch)?;
+    let base_commit = comparison_base(&repo, base_commit, &head_commit, options.mode)?;
+
+    let contents = file_contents(&repo, file_path, Some(&base_commit), &head_commit, options, true)?;
+    Ok(collect_file_blobs(file_path, contents, max_bytes))
+}
+
+/// Raw contents of a file between two arbitrary revisions
+pub fn get_file_blobs_between(
+    repo_path: &str,
+    file_path: &str,
+    base_rev: &str,
+    head_rev: &str,
+    max_bytes: Option<u64>,
+    options: &CompareOptions,
+) -> Result<FileBlobs, String> {
+    let repo = Repository::discover(repo_path).map_err(|e| e.message().to_string())?;
+
+    let head_commit = resolve_revision(&repo, head_rev)?;
+    let base_commit = resolve_revision(&repo, base_rev)?;
+    let base_commit = comparison_base(&repo, base_commit, &head_commit, options.mode)?;
+
+    let contents = file_contents(&repo, file_path, Some(&base_commit), &head_commit, options, false)?;
+    Ok(collect_file_blobs(file_path, contents, max_bytes))
+}
+
+fn collect_file_blobs(file_path: &str, contents: FileContents, max_bytes: Option<u64>) -> FileBlobs {
+    let max_bytes = max_bytes.unwrap_or(media::DEFAULT_MAX_BYTES);
+    let FileContents {
+        old_path,
+        old,
+        new,
+        old_lfs,
+        new_lfs,
+    } = contents;
+
+    FileBlobs {
+        path: file_path.to_string(),
+        old: old.map(|content| media::describe_blob(&old_path, &content, max_bytes)),
+        new: new.map(|content| media::describe_blob(file_path, &content, max_bytes)),
+        old_path: (old_path != file_path).then_some(old_path),
+        old_lfs,
+        new_lfs,
+    }
+}
+
+/// Contents of a blob at `path` in a tree, or `None` if the path does not exist there
+fn blob_at(repo: &Repository, tree: &git2::Tree, path: &str) -> Option<Vec<u8>> {
+    tree.get_path(Path::new(path))
+        .and_then(|entry| entry.to_object(repo))
+        .ok()
+        .and_then(|obj| obj.into_blob().ok())
+        .map(|blob| blob.content().to_vec())
+}
+
+/// Both sides of a file; a side is `None` where the file does not exist
+struct FileContents {
+    old_path: String,
+    old: Option<Vec<u8>>,
+    new: Option<Vec<u8>>,
+    old_lfs: Option<LfsInfo>,
+    new_lfs: Option<LfsInfo>,
+}
+
+/// Full old and new contents of a file. The old side follows renames back to the source path;
+/// the new side is the working tree copy when comparing against the working directory.
+fn file_contents(
+    repo: &Repository,
+    file_path: &str,
+    base_commit: Option<&Commit>,
+    head_commit: &Commit,
+    options: &CompareOptions,
+    include_workdir: bool,
+) -> Result<FileContents, String> {
+    let trees = compare_trees(repo, base_commit, head_commit, options.mode)?;
+
+    let diff = if include_workdir {
+        path_diff(repo, file_path, options, |opts| {
+            opts.include_untracked(true).recurse_untracked_dirs(true);
+            repo.diff_tree_to_workdir_with_index(Some(&trees.base), Some(opts))
+        })?
+    } else {
+        path_diff(repo, file_path, options, |opts| {
+            repo.diff_tree_to_tree(Some(&trees.base), Some(&trees.branch), Some(opts))
+        })?
+    };
+
+    let old_path = diff
+        .deltas()
+        .find(|delta| delta_path(delta) == file_path)
+        .and_then(|delta| delta_old_path(&delta))
+        .unwrap_or_else(|| file_path.to_string());
+
+    let old = blob_at(repo, &trees.base, &old_path);
+    let new = if include_workdir {
+        let workdir = repo.workdir().ok_or("No working directory")?;
+        let path = workdir.join(file_path);
+        // Like git, take a symlink's target as its content rather than following it
+        match std::fs::read_link(&path) {
+            Ok(target) => Some(target.to_string_lossy().as_bytes().to_vec()),
+            Err(_) => std::fs::read(&path).ok(),
+        }
+    } else {
+        blob_at(repo, &trees.branch, file_path)
+    };
+
+    // LFS pointers stand for the objects they point to
+    let [(old, old_lfs), (new, new_lfs)] = lfs::resolve(repo, old, new);
+
+    Ok(FileContents {
+        old_path,
+        old,
+        new,
+        old_lfs,
+        new_lfs,
+    })
+}
+
+/// For each (0-based) line on one side, the 1-based line number of its counterpart on the other side
+type LineMap = Vec<Option<u32>>;
+
+/// Map every line of the old and new side to its counterpart on the other side, using the same
+/// algorithm and whitespace settings as the file diff. Lines inside changed regions have no counterpart.
+fn line_mapping(
+    repo: &Repository,
+    old_lines: &[&str],
+    new_lines: &[&str],
+    options: &CompareOptions,
+) -> Result<(LineMap, LineMap), String> {
+    let (old_count, new_count) = (old_lines.len(), new_lines.len());
+    let mut old_to_new = vec![None; old_count];
+    let mut new_to_old = vec![None; new_count];
+
+    if effective_algorithm(repo, options) == DiffAlgorithm::Histogram {
+        let compare = line_compare(options);
+        let script = histogram::diff_lines(old_lines, new_lines, compare);
+        let ignored = histogram::ignored_changes(old_lines, new_lines, &script, compare);
+        // Blank lines of an ignored change pair up in order, as far as both sides have them
+        let (mut deleted, mut inserted) = (Vec::new(), Vec::new());
+        let mut pairs = Vec::new();
+        for (edit, ignored) in script.into_iter().zip(ignored) {
+            match edit {
+                histogram::Edit::Equal(a, b) => {
+                    pairs.extend(deleted.drain(..).zip(inserted.drain(..)));
+                    pairs.push((a, b));
+                }
+                histogram::Edit::Delete(a) if ignored => deleted.push(a),
+                histogram::Edit::Insert(b) if ignored => inserted.push(b),
+                _ => {}
+            }
+        }
+        pairs.extend(deleted.into_iter().zip(inserted));
+        for (a, b) in pairs {
+            old_to_new[a] = Some(b as u32 + 1);
+            new_to_old[b] = Some(a as u32 + 1);
+        }
+        return Ok((old_to_new, new_to_old));
+    }
+
+    let mut opts = diff_options(repo, options);
+    opts.context_lines(0);
+
+    let (old, new) = (old_lines.concat(), new_lines.concat());
+    let patch = Patch::from_buffers(old.as_bytes(), None, new.as_bytes(), None, Some(&mut opts))
+        .map_err(|e| e.message().to_string())?;
+    let mut pair_unchanged = |from_old: usize, from_new: usize, count: usize| {
+        for i in 0..count {
+            if let (Some(o), Some(n)) = (old_to_new.get_mut(from_old + i), new_to_old.get_mut(from_new + i)) {
+                *o = Some((from_new + i + 1) as u32);
+                *n = Some((from_old + i + 1) as u32);
+            }
+        }
+    };
+
+    // 0-based cursors into the old and new line lists
+    let (mut old_pos, mut new_pos) = (0usize, 0usize);
+    for hunk_idx in 0..patch.num_hunks() {
+        let (hunk, _) = patch.hunk(hunk_idx).map_err(|e| e.message().to_string())?;
+
+        // An empty side's start is the line *before* the change
+        let old_begin = if hunk.old_lines() == 0 { hunk.old_start() } else { hunk.old_start() - 1 } as usize;
+        let unchanged = old_begin.saturating_sub(old_pos);
+
+        pair_unchanged(old_pos, new_pos, unchanged);
+        old_pos = old_begin + hunk.old_lines() as usize;
+        new_pos += unchanged + hunk.new_lines() as usize;
+    }
+    pair_unchanged(old_pos, new_pos, old_count.saturating_sub(old_pos));
+
+    Ok((old_to_new, new_to_old))
+}
+
+/// Content of a side as its file diff shows it, converted if the path has a textconv driver
+fn textconv_side(repo: &Repository, path: &str, content: Vec<u8>) -> Vec<u8> {
+    let DiffDriver::Textconv(command) = attributes::diff_driver(repo, path) else {
+        return content;
+    };
+    attributes::textconv(repo, &command, path, &content).unwrap_or(content)
+}
+
+fn collect_file_lines(
+    repo: &Repository,
+    file_path: &str,
+    contents: FileContents,
+    side: DiffSide,
+    range: RangeInclusive<u32>,
+    options: &CompareOptions,
+) -> Result<FileLines, String> {
+    let old = textconv_side(repo, &contents.old_path, contents.old.unwrap_or_default());
+    let new = textconv_side(repo, file_path, contents.new.unwrap_or_default());
+    let text = |content: &[u8]| match side_text(content, options) {
+        Some(decoded) => decoded.text,
+        None => String::from_utf8_lossy(content).to_string(),
+    };
+    let (old_text, new_text) = (text(&old), text(&new));
+    let old_lines: Vec<&str> = old_text.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new_text.split_inclusive('\n').collect();
+
+    let (old_to_new, new_to_old) = line_mapping(repo, &old_lines, &new_lines, options)?;
+
+    let (side_lines, counterparts) = match side {
+        DiffSide::Old => (&old_lines, &old_to_new),
+        DiffSide::New => (&new_lines, &new_to_old),
+    };
+
+    let first = (*range.start()).max(1) as usize;
+    let last = (*range.end() as usize).min(side_lines.len());
+
+    let tokens = options
+        .syntax_highlight
+        .then(|| match side {
+            DiffSide::Old => syntax::highlight(&contents.old_path, &old_text, last),
+            DiffSide::New => syntax::highlight(file_path, &new_text, last),
+        })
+        .flatten();
+
+    let lines = (first..=last)
+        .map(|lineno| {
+            let counterpart = counterparts[lineno - 1];
+            let (line_type, old_lineno, new_lineno) = match (side, counterpart) {
+                (DiffSide::Old, Some(other)) => ("context", Some(lineno as u32), Some(other)),
+                (DiffSide::Old, None) => ("delete", Some(lineno as u32), None),
+                (DiffSide::New, Some(other)) => ("context", Some(other), Some(lineno as u32)),
+                (DiffSide::New, None) => ("add", None, Some(lineno as u32)),
+            };
+
+            DiffLine {
+                content: side_lines[lineno - 1].to_string(),
+                line_type: line_type.to_string(),
+                old_lineno,
+                new_lineno,
+                highlights: Vec::new(),
+                moved: None,
+                tokens: tokens
+                    .as_ref()
+                    .and_then(|tokens| tokens.get(lineno - 1).cloned())
+                    .unwrap_or_default(),
+                cosmetic_reason: None,
+            }
+        })
+        .collect();
+
+    Ok(FileLines {
+        path: file_path.to_string(),
+        side,
+        total_lines: side_lines.len() as u32,
+        lines,
+    })
+}
+
+/// Commits on HEAD that are not on the base branch, oldest first
+pub fn get_branch_commits(repo_path: &str, base_branch: &str) -> Result<Vec<CommitInfo>, String> {
+    let repo = Repository::discover(repo_path).map_err(|e| e.message().to_string())?;
+
+    let head_commit = repo
+        .head()
+        .map_err(|e| e.message().to_string())?
+        .peel_to_commit()
+        .map_err(|e| e.message().to_string())?;
+
+    let base_commit = resolve_branch_commit(&repo, base_branch)?;
+
+    collect_commits(&repo, &base_commit, &head_commit)
+}
+
+/// Commits reachable from `head_rev` but not from `base_rev`, oldest first
+pub fn get_commits_between(repo_path: &str, base_rev: &str, head_rev: &str) -> Result<Vec<CommitInfo>, String> {
+    let repo = Repository::discover(repo_path).map_err(|e| e.message().to_string())?;
+
+    let head_commit = resolve_revision(&repo, head_rev)?;
+    let base_commit = resolve_revision(&repo, base_rev)?;
+
+    collect_commits(&repo, &base_commit, &head_commit)
+}
+
+/// Commits reachable from head but not from base (all of them without a base), oldest first
+fn range_oids(repo: &Repository, base_commit: Option<&Commit>, head_commit: &Commit) -> Result<Vec<Oid>, String> {
+    let mut revwalk = repo.revwalk().map_err(|e| e.message().to_string())?;
+    revwalk
+        .set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)
+        .map_err(|e| e.message().to_string())?;
+    revwalk.push(head_commit.id()).map_err(|e| e.message().to_string())?;
+    if let Some(base_commit) = base_commit {
+        revwalk.hide(base_commit.id()).map_err(|e| e.message().to_string())?;
+    }
+
+    revwalk
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.message().to_string())
+}
+
+/// The commits named in `selection`, in range order. Every one of them must belong to the compared range.
+fn selected_commits<'r>(
+    repo: &'r Repository,
+    base_commit: Option<&Commit>,
+    head_commit: &Commit,
+    selection: &[String],
+) -> Result<Vec<Commit<'r>>, String> {
+    let mut selected = Vec::with_capacity(selection.len());
+    for rev in selection {
+        selected.push(resolve_revision(repo, rev)?.id());
+    }
+
+    let range = range_oids(repo, base_commit, head_commit)?;
+    if let Some(outside) = selected.iter().find(|oid| !range.contains(oid)) {
+        return Err(format!("Commit {} is not part of the compared range", outside));
+    }
+
+    range
+        .into_iter()
+        .filter(|oid| selected.contains(oid))
+        .map(|oid| repo.find_commit(oid).map_err(|e| e.message().to_string()))
+        .collect()
+}
+
+/// The trees a comparison diffs
+struct CompareTrees<'r> {
+    /// Old side of the committed and combined layers
+    base: Tree<'r>,
+    /// New side of the committed layer: head's tree, in first-parent mode without what merges from
+    /// base brought in
+    branch: Tree<'r>,
+    /// Head's tree, the old side of the staged layer
+    head: Tree<'r>,
+}
+
+/// The trees for a base and head commit; without a base commit the diff starts from the empty tree
+fn compare_trees<'r>(
+    repo: &'r Repository,
+    base_commit: Option<&Commit>,
+    head_commit: &Commit,
+    mode: CompareMode,
+) -> Result<CompareTrees<'r>, String> {
+    let head = repo.find_tree(head_commit.tree_id()).map_err(|e| e.message().to_string())?;
+    let (base, branch) = match base_commit {
+        Some(base_commit) if mode == CompareMode::FirstParent => first_parent_trees(repo, base_commit, head_commit),
+        Some(base_commit) => repo.find_tree(base_commit.tree_id()).map(|base| (base, head.clone())),
+        None => empty_tree(repo).map(|base| (base, head.clone())),
+    }
+    .map_err(|e| e.message().to_string())?;
+    Ok(CompareTrees { base, branch, head })
+}
+
+fn empty_tree(repo: &Repository) -> Result<Tree<'_>, git2::Error> {
+    repo.find_tree(repo.treebuilder(None)?.write()?)
+}
+
+/// Old and new tree of the branch's own changes in first-parent mode. The old side is where the
+/// first-parent chain of head meets base's history; the new side is head's tree with every merge that
+/// only brought in commits from base reverted against its first parent, keeping the branch's version
+/// where it went on to edit the same lines. Without such merges these are the merge base's tree and
+/// head's, the same as in three-dot mode.
+fn first_parent_trees<'r>(
+    repo: &'r Repository,
+    base_commit: &Commit,
+    head_commit: &Commit,
+) -> Result<(Tree<'r>, Tree<'r>), git2::Error> {
+    let from_base = |oid: Oid| -> Result<bool, git2::Error> {
+        Ok(oid == base_commit.id() || repo.graph_descendant_of(base_commit.id(), oid)?)
+    };
+
+    let mut merges = Vec::new();
+    let mut current = Some(repo.find_commit(head_commit.id())?);
+    while let Some(commit) = current.take() {
+        if from_base(commit.id())? {
+            current = Some(commit);
+            break;
+        }
+        let mut merged_from_base = commit.parent_count() > 1;
+        for oid in commit.parent_ids().skip(1) {
+            merged_from_base &= from_base(oid)?;
+        }
+
+        current = commit.parent(0).ok();
+        if merged_from_base {
+            merges.push(commit);
+        }
+    }
+
+    // A chain that never meets base's history starts from nothing
+    let base = match current {
+        Some(commit) => commit.tree()?,
+        None => empty_tree(repo)?,
+    };
+
+    let mut merge_opts = MergeOptions::new();
+    merge_opts.file_favor(FileFavor::Ours);
+    let mut branch = repo.find_tree(head_commit.tree_id())?;
+    for merge in merges {
+        let mainline = merge.parent(0)?.tree()?;
+        let mut index = repo.merge_trees(&merge.tree()?, &branch, &mainline, Some(&merge_opts))?;
+        resolve_ours(&mut index)?;
+        branch = repo.find_tree(index.write_tree_to(repo)?)?;
+    }
+
+    Ok((base, branch))
+}
+
+/// Resolve the conflicts left in a merged index with "our" side, dropping paths it deleted
+fn resolve_ours(index: &mut Index) -> Result<(), git2::Error> {
+    let conflicts = index.conflicts()?.collect::<Result<Vec<_>, _>>()?;
+    for conflict in conflicts {
+        let entries = [&conflict.our, &conflict.their, &conflict.ancestor];
+        let Some(entry) = entries.into_iter().flatten().next() else {
+            continue;
+        };
+        index.remove_path(Path::new(&*String::from_utf8_lossy(&entry.path)))?;
+
+        if let Some(mut ours) = conflict.our {
+            // Back to stage 0, out of the conflict stages (GIT_INDEX_ENTRY_STAGEMASK)
+            ours.flags &= !0x3000;
+            index.add(&ours)?;
+        }
+    }
+    Ok(())
+}
+
+/// What a comparison diffs, and how the working tree comes into it
+struct DiffScope<'r> {
+    /// Explicitly selected commits, diffed one by one
+    selection: Option<Vec<Commit<'r>>>,
+    trees: CompareTrees<'r>,
+    /// The working tree is compared at all
+    include_workdir: bool,
+    /// Everything from the base to the working tree is diffed at once instead of in layers
+    combined: bool,
+}
+
+fn diff_scope<'r>(
+    repo: &'r Repository,
+    base_commit: Option<&Commit>,
+    head_commit: &Commit<'r>,
+    options: &CompareOptions,
+    include_workdir: bool,
+) -> Result<DiffScope<'r>, String> {
+    let selection = if options.commits.is_empty() {
+        None
+    } else {
+        Some(selected_commits(repo, base_commit, head_commit, &options.commits)?)
+    };
+    // An explicit commit selection only covers what those commits introduced, never the working tree
+    let include_workdir = include_workdir && selection.is_none();
+    // The working tree still holds what merges from base brought in, so first-parent mode stays layered
+    let combined = include_workdir && options.mode != CompareMode::FirstParent && options.view == DiffView::Combined;
+
+    Ok(DiffScope {
+        selection,
+        trees: compare_trees(repo, base_commit, head_commit, options.mode)?,
+        include_workdir,
+        combined,
+    })
+}
+
+/// Diff introduced by a single commit, against its first parent (or nothing for a root commit)
+fn commit_diff<'r>(
+    repo: &'r Repository,
+    commit: &Commit,
+    opts: &mut DiffOptions,
+) -> Result<git2::Diff<'r>, git2::Error> {
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), Some(opts))
+}
+
+fn collect_commits(repo: &Repository, base_commit: &Commit, head_commit: &Commit) -> Result<Vec<CommitInfo>, String> {
+    let mut commits = Vec::new();
+    for oid in range_oids(repo, Some(base_commit), head_commit)? {
+        let commit = repo.find_commit(oid).map_err(|e| e.message().to_string())?;
+
+        let diff = commit_diff(repo, &commit, &mut DiffOptions::new()).map_err(|e| e.message().to_string())?;
+
+        let author = commit.author();
+        commits.push(CommitInfo {
+            oid: oid.to_string(),
+            short_oid: oid.to_string()[..7].to_string(),
+            summary: commit.summary().unwrap_or("").to_string(),
+            author_name: author.name().unwrap_or("").to_string(),
+            author_email: author.email().unwrap_or("").to_string(),
+            time: author.when().seconds(),
+            files: diff.deltas().map(|delta| delta_path(&delta)).collect(),
+        });
+    }
+
+    Ok(commits)
+}
+
+/// The commit a range of commits starts from: the first parent of its oldest commit, `None` when that
+/// is a root commit and the range starts from nothing
+fn range_base<'r>(repo: &'r Repository, from_rev: &str) -> Result<Option<Commit<'r>>, String> {
+    Ok(resolve_revision(repo, from_rev)?.parent(0).ok())
+}
+
+/// Changed files of the commits `from_rev` through `to_rev`; pass the same revision twice for a single commit
+pub fn get_commit_range_files(
+    repo_path: &str,
+    from_rev: &str,
+    to_rev: &str,
+    options: &CompareOptions,
+) -> Result<ChangeSet, String> {
+    let repo = Repository::discover(repo_path).map_err(|e| e.message().to_string())?;
+
+    let head_commit = resolve_revision(&repo, to_rev)?;
+    let base_commit = range_base(&repo, from_rev)?;
+    let options = CompareOptions {
+        mode: CompareMode::TwoDot,
+        ..options.clone()
+    };
+
+    collect_changed_files(&repo, base_commit.as_ref(), &head_commit, &options, false)
+}
+
+/// Diff of a single file over the commits `from_rev` through `to_rev`
+pub fn get_commit_range_file_diff(
+    repo_path: &str,
+    file_path: &str,
+    from_rev: &str,
+    to_rev: &str,
+    window: Option<DiffWindow>,
+    options: &CompareOptions,
+) -> Result<FileDiff, String> {
+    let repo = Repository::discover(repo_path).map_err(|e| e.message().to_string())?;
+
+    let head_commit = resolve_revision(&repo, to_rev)?;
+    let base_commit = range_base(&repo, from_rev)?;
+    let options = CompareOptions {
+        mode: CompareMode::TwoDot,
+        ..options.clone()
+    };
+
+    collect_file_diff(&repo, file_path, base_commit.as_ref(), &head_commit, window, &options, false)
+}
+
+/// Commits and changed files inside the submodule at `submodule_path` between two of its commits,
+/// typically the `old_oid` and `new_oid` of a submodule entry. The submodule must be checked out
+/// and contain both commits.
+pub fn get_submodule_summary(
+    repo_path: &str,
+    submodule_path: &str,
+    old_oid: &str,
+    new_oid: &str,
+    options: &CompareOptions,
+) -> Result<SubmoduleSummary, String> {
+    let repo = Repository::discover(repo_path).map_err(|e| e.message().to_string())?;
+
+    let sub_repo = repo
+        .find_submodule(submodule_path)
+        .and_then(|submodule| submodule.open())
+        .map_err(|e| format!("Could not open submodule '{}': {}", submodule_path, e.message()))?;
+
+    let old_commit = resolve_revision(&sub_repo, old_oid)?;
+    let new_commit = resolve_revision(&sub_repo, new_oid)?;
+    let options = CompareOptions {
+        mode: CompareMode::TwoDot,
+        commits: Vec::new(),
+        ..options.clone()
+    };
+
+    Ok(SubmoduleSummary {
+        repo_path: sub_repo
+            .workdir()
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        commits: collect_commits(&sub_repo, &old_commit, &new_commit)?,
+        removed_commits: collect_commits(&sub_repo, &new_commit, &old_commit)?,
+        changes: collect_changed_files(&sub_repo, Some(&old_commit), &new_commit, &options, false)?,
     })
 }
 
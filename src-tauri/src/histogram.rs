@@ -0,0 +1,305 @@
+use crate::git_ops::{DiffHunk, DiffLine};
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+use std::collections::HashMap;
+
+/// Lines occurring more often than this in a region make histogram matching quadratic;
+/// such regions are handed to Myers instead, like git does
+const MAX_CHAIN_LEN: usize = 64;
+
+/// How lines are compared, mirroring git's whitespace flags
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LineCompare {
+    pub ignore_whitespace: bool,
+    pub ignore_whitespace_change: bool,
+    pub ignore_whitespace_eol: bool,
+    /// Changes that only add or remove blank lines don't count
+    pub ignore_blank_lines: bool,
+}
+
+impl LineCompare {
+    /// Comparison key of a line under these settings
+    pub fn key(&self, line: &str) -> String {
+        // A carriage return only counts as whitespace; line endings are normalized up front if at all
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        if self.ignore_whitespace {
+            line.chars().filter(|c| !c.is_whitespace()).collect()
+        } else if self.ignore_whitespace_change {
+            // Every whitespace run counts as a single space, trailing whitespace not at all
+            let mut key = String::with_capacity(line.len());
+            for c in line.trim_end().chars() {
+                if !c.is_whitespace() {
+                    key.push(c);
+                } else if !key.ends_with(' ') || key.is_empty() {
+                    key.push(' ');
+                }
+            }
+            key
+        } else if self.ignore_whitespace_eol {
+            line.trim_end().to_string()
+        } else {
+            line.to_string()
+        }
+    }
+}
+
+/// One step of an edit script: a line kept, deleted from the old side, or inserted from the new side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Histogram diff of two texts, as an edit script over their lines
+pub fn diff_lines(old: &[&str], new: &[&str], compare: LineCompare) -> Vec<Edit> {
+    // Intern comparison keys so the matching below works on integers
+    let mut interner: HashMap<String, usize> = HashMap::new();
+    let mut intern = |line: &&str| {
+        let next = interner.len();
+        *interner.entry(compare.key(line)).or_insert(next)
+    };
+    let old_ids: Vec<usize> = old.iter().map(&mut intern).collect();
+    let new_ids: Vec<usize> = new.iter().map(&mut intern).collect();
+
+    // Like git, match the common prefix and suffix up front so anchors are only searched in between
+    let prefix = old_ids.iter().zip(&new_ids).take_while(|(a, b)| a == b).count();
+    let suffix = old_ids[prefix..]
+        .iter()
+        .rev()
+        .zip(new_ids[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_end, new_end) = (old_ids.len() - suffix, new_ids.len() - suffix);
+
+    let mut matches: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    matches.extend((0..suffix).map(|i| (old_end + i, new_end + i)));
+    let mut regions = vec![(prefix, old_end, prefix, new_end)];
+
+    while let Some((a0, a1, b0, b1)) = regions.pop() {
+        if a0 == a1 || b0 == b1 {
+            continue;
+        }
+
+        match find_anchor(&old_ids, &new_ids, a0, a1, b0, b1) {
+            Anchor::Found(as_, ae, bs, be) => {
+                matches.extend((0..ae - as_).map(|i| (as_ + i, bs + i)));
+                regions.push((a0, as_, b0, bs));
+                regions.push((ae, a1, be, b1));
+            }
+            Anchor::None => {}
+            Anchor::TooCommon => {
+                let ops = capture_diff_slices(Algorithm::Myers, &old_ids[a0..a1], &new_ids[b0..b1]);
+                for op in ops.iter().filter(|op| op.tag() == DiffTag::Equal) {
+                    let (old_range, new_range) = (op.old_range(), op.new_range());
+                    matches.extend(old_range.zip(new_range).map(|(a, b)| (a0 + a, b0 + b)));
+                }
+            }
+        }
+    }
+
+    matches.sort_unstable();
+
+    let mut script = Vec::with_capacity(old.len().max(new.len()));
+    let (mut a, mut b) = (0, 0);
+    for (ma, mb) in matches.into_iter().chain(std::iter::once((old.len(), new.len()))) {
+        script.extend((a..ma).map(Edit::Delete));
+        script.extend((b..mb).map(Edit::Insert));
+        if ma < old.len() {
+            script.push(Edit::Equal(ma, mb));
+        }
+        (a, b) = (ma + 1, mb + 1);
+    }
+    script
+}
+
+enum Anchor {
+    /// Matching region `old[as..ae] == new[bs..be]`
+    Found(usize, usize, usize, usize),
+    /// The regions share no lines
+    None,
+    /// Every shared line is too frequent to anchor on
+    TooCommon,
+}
+
+/// Find the longest matching region built around the least frequent line common to both sides
+fn find_anchor(old: &[usize], new: &[usize], a0: usize, a1: usize, b0: usize, b1: usize) -> Anchor {
+    let mut occurrences: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, id) in old.iter().enumerate().take(a1).skip(a0) {
+        occurrences.entry(*id).or_default().push(i);
+    }
+
+    let mut best: Option<(usize, usize, usize, usize)> = None;
+    let mut best_count = MAX_CHAIN_LEN + 1;
+    let mut has_common = false;
+
+    let mut b = b0;
+    while b < b1 {
+        let Some(positions) = occurrences.get(&new[b]) else {
+            b += 1;
+            continue;
+        };
+        has_common = true;
+        if positions.len() > best_count {
+            b += 1;
+            continue;
+        }
+
+        // Lines inside a region already matched from this line would only find the same region
+        let mut next_b = b + 1;
+        for &a in positions {
+            let (mut as_, mut bs) = (a, b);
+            while as_ > a0 && bs > b0 && old[as_ - 1] == new[bs - 1] {
+                as_ -= 1;
+                bs -= 1;
+            }
+            let (mut ae, mut be) = (a + 1, b + 1);
+            while ae < a1 && be < b1 && old[ae] == new[be] {
+                ae += 1;
+                be += 1;
+            }
+            next_b = next_b.max(be);
+
+            let count = (as_..ae)
+                .map(|i| occurrences.get(&old[i]).map_or(usize::MAX, Vec::len))
+                .min()
+                .unwrap_or(usize::MAX);
+            let longer = best.map_or(true, |(bas, bae, _, _)| ae - as_ > bae - bas);
+
+            if count < best_count || (count == best_count && longer) {
+                best = Some((as_, ae, bs, be));
+                best_count = count;
+            }
+        }
+        b = next_b;
+    }
+
+    match best {
+        Some((as_, ae, bs, be)) => Anchor::Found(as_, ae, bs, be),
+        None if has_common => Anchor::TooCommon,
+        None => Anchor::None,
+    }
+}
+
+/// For each step of an edit script, whether it belongs to a run of changes that only adds or
+/// removes blank lines and is therefore ignored, like git's `--ignore-blank-lines`
+pub fn ignored_changes(old: &[&str], new: &[&str], script: &[Edit], compare: LineCompare) -> Vec<bool> {
+    let mut ignored = vec![false; script.len()];
+    if !compare.ignore_blank_lines {
+        return ignored;
+    }
+
+    let is_blank = |edit: &Edit| match *edit {
+        Edit::Equal(..) => false,
+        Edit::Delete(a) => old[a].trim().is_empty(),
+        Edit::Insert(b) => new[b].trim().is_empty(),
+    };
+    let mut start = 0;
+    while start < script.len() {
+        if matches!(script[start], Edit::Equal(..)) {
+            start += 1;
+            continue;
+        }
+        let end = script[start..]
+            .iter()
+            .position(|edit| matches!(edit, Edit::Equal(..)))
+            .map_or(script.len(), |len| start + len);
+        if script[start..end].iter().all(is_blank) {
+            ignored[start..end].fill(true);
+        }
+        start = end;
+    }
+    ignored
+}
+
+/// Group an edit script into hunks with `context` unchanged lines around each change,
+/// numbered the way libgit2 numbers its hunks. Ignored blank-line changes only show up
+/// next to a change that counts.
+pub fn build_hunks(
+    old: &[&str],
+    new: &[&str],
+    script: &[Edit],
+    compare: LineCompare,
+    context: usize,
+    layer: &str,
+) -> Vec<DiffHunk> {
+    let ignored = ignored_changes(old, new, script, compare);
+    let changes: Vec<usize> = script
+        .iter()
+        .enumerate()
+        .filter(|&(i, edit)| !matches!(edit, Edit::Equal(..)) && !ignored[i])
+        .map(|(i, _)| i)
+        .collect();
+
+    // Split into groups of changes separated by more than 2 * context unchanged lines
+    let mut groups: Vec<(usize, usize)> = Vec::new();
+    for &i in &changes {
+        match groups.last_mut() {
+            Some((_, last)) if i - *last <= 2 * context + 1 => *last = i,
+            _ => groups.push((i, i)),
+        }
+    }
+
+    // Old and new lines consumed before each script position
+    let mut positions = Vec::with_capacity(script.len() + 1);
+    let (mut a, mut b) = (0u32, 0u32);
+    for edit in script {
+        positions.push((a, b));
+        match edit {
+            Edit::Equal(..) => {
+                a += 1;
+                b += 1;
+            }
+            Edit::Delete(_) => a += 1,
+            Edit::Insert(_) => b += 1,
+        }
+    }
+    positions.push((a, b));
+
+    groups
+        .into_iter()
+        .map(|(first, last)| {
+            let start = first.saturating_sub(context);
+            let end = (last + context + 1).min(script.len());
+
+            let lines: Vec<DiffLine> = script[start..end]
+                .iter()
+                .map(|edit| {
+                    let (line_type, content, old_lineno, new_lineno) = match *edit {
+                        Edit::Equal(a, b) => ("context", new[b], Some(a as u32 + 1), Some(b as u32 + 1)),
+                        Edit::Delete(a) => ("delete", old[a], Some(a as u32 + 1), None),
+                        Edit::Insert(b) => ("add", new[b], None, Some(b as u32 + 1)),
+                    };
+                    DiffLine {
+                        content: content.to_string(),
+                        line_type: line_type.to_string(),
+                        old_lineno,
+                        new_lineno,
+                        highlights: Vec::new(),
+                        moved: None,
+                        tokens: Vec::new(),
+                        cosmetic_reason: None,
+                    }
+                })
+                .collect();
+
+            let (old_before, new_before) = positions[start];
+            let old_lines = positions[end].0 - old_before;
+            let new_lines = positions[end].1 - new_before;
+
+            DiffHunk {
+                // An empty side starts at the line *before* the change
+                old_start: if old_lines == 0 { old_before } else { old_before + 1 },
+                old_lines,
+                new_start: if new_lines == 0 { new_before } else { new_before + 1 },
+                new_lines,
+                lines,
+                is_cosmetic: false,
+                cosmetic_reason: None,
+                line_endings_changed: false,
+                layer: layer.to_string(),
+                line_offset: 0,
+                commit: None,
+            }
+        })
+        .collect()
+}
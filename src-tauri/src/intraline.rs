@@ -0,0 +1,154 @@
+use crate::git_ops::{DiffLine, Highlight};
+use serde::{Deserialize, Serialize};
+use similar::{capture_diff_slices_deadline, get_diff_ratio, Algorithm, DiffTag};
+use std::time::{Duration, Instant};
+
+/// Granularity of the changed ranges highlighted inside paired lines
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IntralineMode {
+    /// No intra-line highlighting
+    Off,
+    /// Whole identifiers, numbers, whitespace runs and single punctuation characters
+    #[default]
+    Word,
+    /// Individual characters
+    Char,
+}
+
+/// Lines that share less than this fraction of their tokens are shown as entirely
+/// replaced rather than speckled with highlights
+const MIN_SIMILARITY: f32 = 0.5;
+
+/// Lines longer than this are not worth diffing token by token
+const MAX_LINE_LEN: usize = 10_000;
+
+/// Time budget for diffing a single pair of lines
+const LINE_DEADLINE: Duration = Duration::from_millis(20);
+
+/// Pair up the deleted and added lines of a hunk and mark the ranges that changed between them.
+/// A run of deletions directly followed by a run of additions is paired line by line; any surplus
+/// lines on either side have no counterpart and are left without highlights.
+pub fn highlight_hunk(lines: &mut [DiffLine], mode: IntralineMode) {
+    if mode == IntralineMode::Off {
+        return;
+    }
+
+    let mut i = 0;
+    while i < lines.len() {
+        let del_start = i;
+        while i < lines.len() && lines[i].line_type == "delete" {
+            i += 1;
+        }
+        let add_start = i;
+        while i < lines.len() && lines[i].line_type == "add" {
+            i += 1;
+        }
+
+        let pairs = (add_start - del_start).min(i - add_start);
+        for offset in 0..pairs {
+            let (old, new) = lines.split_at_mut(add_start + offset);
+            highlight_pair(&mut old[del_start + offset], &mut new[0], mode);
+        }
+
+        // Skip past context lines (and additions not preceded by deletions)
+        if i == del_start {
+            i += 1;
+        }
+    }
+}
+
+fn highlight_pair(old: &mut DiffLine, new: &mut DiffLine, mode: IntralineMode) {
+    // Moved lines were not edited into each other, they just happen to sit side by side
+    if old.moved.is_some() || new.moved.is_some() {
+        return;
+    }
+
+    let old_text = old.content.trim_end_matches(['\n', '\r']);
+    let new_text = new.content.trim_end_matches(['\n', '\r']);
+
+    if old_text.len() > MAX_LINE_LEN || new_text.len() > MAX_LINE_LEN {
+        return;
+    }
+
+    let old_tokens = tokenize(old_text, mode);
+    let new_tokens = tokenize(new_text, mode);
+
+    let ops = capture_diff_slices_deadline(
+        Algorithm::Myers,
+        &old_tokens,
+        &new_tokens,
+        Some(Instant::now() + LINE_DEADLINE),
+    );
+
+    if get_diff_ratio(&ops, old_tokens.len(), new_tokens.len()) < MIN_SIMILARITY {
+        return;
+    }
+
+    let old_offsets = utf16_offsets(&old_tokens);
+    let new_offsets = utf16_offsets(&new_tokens);
+
+    for op in ops.iter().filter(|op| op.tag() != DiffTag::Equal) {
+        push_highlight(&mut old.highlights, &old_offsets, op.old_range());
+        push_highlight(&mut new.highlights, &new_offsets, op.new_range());
+    }
+}
+
+/// Split a line into diffable tokens
+fn tokenize(text: &str, mode: IntralineMode) -> Vec<&str> {
+    if mode == IntralineMode::Char {
+        return text.char_indices().map(|(i, c)| &text[i..i + c.len_utf8()]).collect();
+    }
+
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            1
+        } else if c.is_whitespace() {
+            2
+        } else {
+            // Punctuation is always a token of its own
+            3
+        }
+    };
+
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut prev_class = 0;
+    for (i, c) in text.char_indices() {
+        let current = class(c);
+        if i > start && (current != prev_class || current == 3) {
+            tokens.push(&text[start..i]);
+            start = i;
+        }
+        prev_class = current;
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+/// UTF-16 offset of the start of each token, plus the end of the last one
+fn utf16_offsets(tokens: &[&str]) -> Vec<u32> {
+    let mut offsets = Vec::with_capacity(tokens.len() + 1);
+    let mut offset = 0u32;
+    offsets.push(offset);
+    for token in tokens {
+        offset += token.encode_utf16().count() as u32;
+        offsets.push(offset);
+    }
+    offsets
+}
+
+/// Add the token range as a highlight, merging it into the previous one when they touch
+fn push_highlight(highlights: &mut Vec<Highlight>, offsets: &[u32], range: std::ops::Range<usize>) {
+    if range.is_empty() {
+        return;
+    }
+
+    let (start, end) = (offsets[range.start], offsets[range.end]);
+    match highlights.last_mut() {
+        Some(last) if last.end == start => last.end = end,
+        _ => highlights.push(Highlight { start, end }),
+    }
+}
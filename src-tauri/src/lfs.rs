@@ -0,0 +1,90 @@
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// First line of every Git LFS pointer file
+const POINTER_VERSION: &str = "version https://git-lfs.github.com/spec/v1";
+
+/// Pointer files are tiny; anything larger is real content
+const MAX_POINTER_LEN: usize = 1024;
+
+/// One side of a file stored in Git LFS
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LfsInfo {
+    /// SHA-256 of the real content
+    pub oid: String,
+    /// Size of the real content in bytes
+    pub size: u64,
+    /// The object was found in the local LFS store and its content is what got compared.
+    /// Otherwise the pointer file itself stands in for it.
+    pub available: bool,
+}
+
+/// Replace the contents of LFS pointer files on the old and new side of a file with the objects they
+/// point to. That only happens when every pointer's object is in the local store; otherwise both sides
+/// stay pointers and report `available: false`, so an object is never compared with pointer text.
+/// Content that is not a pointer comes back unchanged, without `LfsInfo`.
+pub fn resolve(
+    repo: &Repository,
+    old: Option<Vec<u8>>,
+    new: Option<Vec<u8>>,
+) -> [(Option<Vec<u8>>, Option<LfsInfo>); 2] {
+    let sides = [old, new].map(|content| {
+        let pointer = content.as_deref().and_then(parse_pointer);
+        let object = pointer.as_ref().and_then(|(oid, size)| {
+            std::fs::read(object_path(repo, oid)).ok().filter(|object| object.len() as u64 == *size)
+        });
+        (content, pointer, object)
+    });
+    let available = sides.iter().all(|(_, pointer, object)| pointer.is_none() || object.is_some());
+
+    sides.map(|(content, pointer, object)| match pointer {
+        Some((oid, size)) => (
+            object.filter(|_| available).or(content),
+            Some(LfsInfo { oid, size, available }),
+        ),
+        None => (content, None),
+    })
+}
+
+/// Object id and size of a pointer file
+fn parse_pointer(content: &[u8]) -> Option<(String, u64)> {
+    if content.len() > MAX_POINTER_LEN {
+        return None;
+    }
+    let text = std::str::from_utf8(content).ok()?;
+    let mut lines = text.lines();
+    if lines.next()? != POINTER_VERSION {
+        return None;
+    }
+
+    let (mut oid, mut size) = (None, None);
+    for line in lines {
+        if let Some(hash) = line.strip_prefix("oid sha256:") {
+            oid = Some(hash).filter(|hash| hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()));
+        } else if let Some(value) = line.strip_prefix("size ") {
+            size = value.parse().ok();
+        }
+    }
+    Some((oid?.to_lowercase(), size?))
+}
+
+/// Where git-lfs keeps an object: `lfs/objects/ab/cd/abcd...` under the common git directory,
+/// unless `lfs.storage` points elsewhere
+fn object_path(repo: &Repository, oid: &str) -> PathBuf {
+    // Linked worktrees share the LFS store of the main repository
+    let mut common_dir = repo.path().to_path_buf();
+    if repo.is_worktree() {
+        if let Ok(relative) = std::fs::read_to_string(repo.path().join("commondir")) {
+            common_dir = common_dir.join(relative.trim());
+        }
+    }
+
+    let storage = repo
+        .config()
+        .and_then(|config| config.get_path("lfs.storage"))
+        .map(|storage| common_dir.join(storage))
+        .unwrap_or_else(|_| common_dir.join("lfs"));
+
+    storage.join("objects").join(&oid[0..2]).join(&oid[2..4]).join(oid)
+}
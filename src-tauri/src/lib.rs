@@ -1,7 +1,21 @@
+mod attributes;
+mod classify;
+mod comments;
+mod encoding;
 mod git_ops;
 mod git_watcher;
+mod histogram;
+mod intraline;
+mod lfs;
+mod media;
+mod moved;
+mod syntax;
+mod syntax_tree;
 
-use git_ops::{ChangedFile, FileDiff, RepoInfo};
+use git_ops::{
+    ChangeSet, CommitInfo, CompareOptions, DiffSide, DiffWindow, FileBlobs, FileDiff, FileLines, RepoInfo,
+    SubmoduleSummary,
+};
 use git_watcher::{create_watcher_handle, WatcherHandle};
 
 #[tauri::command]
@@ -10,13 +24,169 @@ fn get_repo_info(repo_path: String) -> Result<RepoInfo, String> {
 }
 
 #[tauri::command]
-fn get_changed_files(repo_path: String, base_branch: String) -> Result<Vec<ChangedFile>, String> {
-    git_ops::get_changed_files(&repo_path, &base_branch)
+fn get_changed_files(
+    repo_path: String,
+    base_branch: String,
+    options: Option<CompareOptions>,
+) -> Result<ChangeSet, String> {
+    git_ops::get_changed_files(&repo_path, &base_branch, &options.unwrap_or_default())
 }
 
 #[tauri::command]
-fn get_file_diff(repo_path: String, file_path: String, base_branch: String) -> Result<FileDiff, String> {
-    git_ops::get_file_diff(&repo_path, &file_path, &base_branch)
+fn get_file_diff(
+    repo_path: String,
+    file_path: String,
+    base_branch: String,
+    window: Option<DiffWindow>,
+    options: Option<CompareOptions>,
+) -> Result<FileDiff, String> {
+    git_ops::get_file_diff(&repo_path, &file_path, &base_branch, window, &options.unwrap_or_default())
+}
+
+#[tauri::command]
+fn get_changed_files_between(
+    repo_path: String,
+    base_rev: String,
+    head_rev: String,
+    options: Option<CompareOptions>,
+) -> Result<ChangeSet, String> {
+    git_ops::get_changed_files_between(&repo_path, &base_rev, &head_rev, &options.unwrap_or_default())
+}
+
+#[tauri::command]
+fn get_file_diff_between(
+    repo_path: String,
+    file_path: String,
+    base_rev: String,
+    head_rev: String,
+    window: Option<DiffWindow>,
+    options: Option<CompareOptions>,
+) -> Result<FileDiff, String> {
+    git_ops::get_file_diff_between(
+        &repo_path,
+        &file_path,
+        &base_rev,
+        &head_rev,
+        window,
+        &options.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+fn get_file_lines(
+    repo_path: String,
+    file_path: String,
+    base_branch: String,
+    side: DiffSide,
+    start: u32,
+    end: u32,
+    options: Option<CompareOptions>,
+) -> Result<FileLines, String> {
+    git_ops::get_file_lines(&repo_path, &file_path, &base_branch, side, start, end, &options.unwrap_or_default())
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn get_file_lines_between(
+    repo_path: String,
+    file_path: String,
+    base_rev: String,
+    head_rev: String,
+    side: DiffSide,
+    start: u32,
+    end: u32,
+    options: Option<CompareOptions>,
+) -> Result<FileLines, String> {
+    git_ops::get_file_lines_between(
+        &repo_path,
+        &file_path,
+        &base_rev,
+        &head_rev,
+        side,
+        start,
+        end,
+        &options.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+fn get_file_blobs(
+    repo_path: String,
+    file_path: String,
+    base_branch: String,
+    max_bytes: Option<u64>,
+    options: Option<CompareOptions>,
+) -> Result<FileBlobs, String> {
+    git_ops::get_file_blobs(&repo_path, &file_path, &base_branch, max_bytes, &options.unwrap_or_default())
+}
+
+#[tauri::command]
+fn get_file_blobs_between(
+    repo_path: String,
+    file_path: String,
+    base_rev: String,
+    head_rev: String,
+    max_bytes: Option<u64>,
+    options: Option<CompareOptions>,
+) -> Result<FileBlobs, String> {
+    git_ops::get_file_blobs_between(
+        &repo_path,
+        &file_path,
+        &base_rev,
+        &head_rev,
+        max_bytes,
+        &options.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+fn get_branch_commits(repo_path: String, base_branch: String) -> Result<Vec<CommitInfo>, String> {
+    git_ops::get_branch_commits(&repo_path, &base_branch)
+}
+
+#[tauri::command]
+fn get_commits_between(repo_path: String, base_rev: String, head_rev: String) -> Result<Vec<CommitInfo>, String> {
+    git_ops::get_commits_between(&repo_path, &base_rev, &head_rev)
+}
+
+#[tauri::command]
+fn get_commit_range_files(
+    repo_path: String,
+    from_rev: String,
+    to_rev: String,
+    options: Option<CompareOptions>,
+) -> Result<ChangeSet, String> {
+    git_ops::get_commit_range_files(&repo_path, &from_rev, &to_rev, &options.unwrap_or_default())
+}
+
+#[tauri::command]
+fn get_commit_range_file_diff(
+    repo_path: String,
+    file_path: String,
+    from_rev: String,
+    to_rev: String,
+    window: Option<DiffWindow>,
+    options: Option<CompareOptions>,
+) -> Result<FileDiff, String> {
+    git_ops::get_commit_range_file_diff(
+        &repo_path,
+        &file_path,
+        &from_rev,
+        &to_rev,
+        window,
+        &options.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+fn get_submodule_summary(
+    repo_path: String,
+    submodule_path: String,
+    old_oid: String,
+    new_oid: String,
+    options: Option<CompareOptions>,
+) -> Result<SubmoduleSummary, String> {
+    git_ops::get_submodule_summary(&repo_path, &submodule_path, &old_oid, &new_oid, &options.unwrap_or_default())
 }
 
 #[tauri::command]
@@ -54,6 +224,17 @@ pub fn run() {
             get_repo_info,
             get_changed_files,
             get_file_diff,
+            get_changed_files_between,
+            get_file_diff_between,
+            get_file_lines,
+            get_file_lines_between,
+            get_file_blobs,
+            get_file_blobs_between,
+            get_branch_commits,
+            get_commits_between,
+            get_commit_range_files,
+            get_commit_range_file_diff,
+            get_submodule_summary,
             open_in_editor,
             watch_repo,
             stop_watching,
@@ -0,0 +1,117 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use imagesize::ImageType;
+use serde::{Deserialize, Serialize};
+
+/// Blobs larger than this are described but their bytes are not sent to the UI
+pub const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// One side of a binary or image comparison
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlobInfo {
+    /// Size in bytes
+    pub size: u64,
+    pub mime_type: String,
+    /// Pixel dimensions for recognized image formats
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Base64 encoded content, `None` when the blob exceeds the size cap
+    pub data: Option<String>,
+}
+
+/// Describe a blob, including its content if it is no larger than `max_bytes`
+pub fn describe_blob(path: &str, content: &[u8], max_bytes: u64) -> BlobInfo {
+    let size = content.len() as u64;
+    let mime_type = mime_type(path, content);
+
+    let (width, height) = if mime_type == "image/svg+xml" {
+        svg_dimensions(content)
+    } else {
+        imagesize::blob_size(content)
+            .map(|dim| (Some(dim.width as u32), Some(dim.height as u32)))
+            .unwrap_or((None, None))
+    };
+
+    BlobInfo {
+        size,
+        mime_type,
+        width,
+        height,
+        data: (size <= max_bytes).then(|| STANDARD.encode(content)),
+    }
+}
+
+/// MIME type from the content's magic bytes for common image formats, otherwise from the file extension
+fn mime_type(path: &str, content: &[u8]) -> String {
+    let sniffed = match imagesize::image_type(content) {
+        Ok(ImageType::Png) => Some("image/png"),
+        Ok(ImageType::Jpeg) => Some("image/jpeg"),
+        Ok(ImageType::Gif) => Some("image/gif"),
+        Ok(ImageType::Webp) => Some("image/webp"),
+        Ok(ImageType::Bmp) => Some("image/bmp"),
+        Ok(ImageType::Ico) => Some("image/x-icon"),
+        Ok(ImageType::Tiff) => Some("image/tiff"),
+        _ => None,
+    };
+
+    match sniffed {
+        Some(mime) => mime.to_string(),
+        None => mime_guess::from_path(path).first_or_octet_stream().to_string(),
+    }
+}
+
+/// Size of an SVG from the `width`/`height` attributes of its root element, falling back to the `viewBox`
+fn svg_dimensions(content: &[u8]) -> (Option<u32>, Option<u32>) {
+    let text = String::from_utf8_lossy(content);
+    let Some(start) = text.find("<svg") else {
+        return (None, None);
+    };
+    let tag = &text[start..text[start..].find('>').map_or(text.len(), |end| start + end)];
+
+    let length = |name: &str| {
+        svg_attribute(tag, name).and_then(|value| {
+            // Only plain numbers and pixel lengths; percentages and physical units have no pixel size
+            let value = value.trim().trim_end_matches("px");
+            value.parse::<f64>().ok().map(|v| v.round() as u32)
+        })
+    };
+
+    match (length("width"), length("height")) {
+        (Some(width), Some(height)) => (Some(width), Some(height)),
+        _ => {
+            let view_box: Vec<f64> = svg_attribute(tag, "viewBox")
+                .unwrap_or_default()
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter_map(|part| part.parse().ok())
+                .collect();
+            match view_box[..] {
+                [_, _, width, height] => (Some(width.round() as u32), Some(height.round() as u32)),
+                _ => (None, None),
+            }
+        }
+    }
+}
+
+/// Value of an attribute in an XML start tag
+fn svg_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    while let Some(pos) = rest.find(name) {
+        let before = rest[..pos].chars().next_back();
+        let after = rest[pos + name.len()..].trim_start();
+        rest = &rest[pos + name.len()..];
+
+        if !before.is_some_and(char::is_whitespace) {
+            continue;
+        }
+        let Some(value) = after.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let quote = value.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            continue;
+        }
+        return value[1..].find(quote).map(|end| &value[1..end + 1]);
+    }
+    None
+}
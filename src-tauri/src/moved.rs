@@ -0,0 +1,115 @@
+use crate::git_ops::{DiffLine, MoveLink};
+use crate::histogram::LineCompare;
+use std::collections::HashMap;
+
+/// A block must contain at least this many alphanumeric characters to count as moved,
+/// so stray braces and blank lines that happen to match elsewhere are left alone (same as git)
+const MIN_ALNUM_COUNT: usize = 20;
+
+/// A deleted or added line taking part in move detection
+struct Candidate {
+    file: usize,
+    line: usize,
+    lineno: u32,
+    key: String,
+}
+
+/// Find blocks of deleted lines that reappear as added lines, within or across files,
+/// like `git diff --color-moved`, and link every line of such a block to its counterpart.
+/// Each file comes with its changed lines in diff order.
+pub fn mark_moves(files: &mut [(&str, Vec<&mut DiffLine>)], compare: LineCompare) {
+    let mut deletes = Vec::new();
+    let mut adds = Vec::new();
+
+    for (file, (_, lines)) in files.iter().enumerate() {
+        for (line, diff_line) in lines.iter().enumerate() {
+            let (list, lineno) = match diff_line.line_type.as_str() {
+                "delete" => (&mut deletes, diff_line.old_lineno),
+                "add" => (&mut adds, diff_line.new_lineno),
+                _ => continue,
+            };
+            let Some(lineno) = lineno else {
+                continue;
+            };
+            list.push(Candidate {
+                file,
+                line,
+                lineno,
+                key: compare.key(&diff_line.content),
+            });
+        }
+    }
+
+    let mut positions: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, candidate) in deletes.iter().enumerate() {
+        positions.entry(candidate.key.as_str()).or_default().push(i);
+    }
+
+    // Whether `next` directly follows `prev` in the same file
+    let follows = |prev: &Candidate, next: &Candidate| prev.file == next.file && next.lineno == prev.lineno + 1;
+
+    let mut used = vec![false; deletes.len()];
+    let mut blocks = Vec::new();
+
+    let mut a = 0;
+    while a < adds.len() {
+        // Blocks never start on a line without any text of its own
+        let starts = positions
+            .get(adds[a].key.as_str())
+            .filter(|_| adds[a].key.chars().any(char::is_alphanumeric));
+
+        let mut best: Option<(usize, usize)> = None;
+        for &d in starts.into_iter().flatten() {
+            if used[d] {
+                continue;
+            }
+
+            let mut len = 1;
+            while a + len < adds.len()
+                && d + len < deletes.len()
+                && !used[d + len]
+                && follows(&adds[a + len - 1], &adds[a + len])
+                && follows(&deletes[d + len - 1], &deletes[d + len])
+                && adds[a + len].key == deletes[d + len].key
+            {
+                len += 1;
+            }
+
+            if best.map_or(true, |(_, best_len)| len > best_len) {
+                best = Some((d, len));
+            }
+        }
+
+        match best {
+            Some((d, len)) if alnum_count(&adds[a..a + len]) >= MIN_ALNUM_COUNT => {
+                used[d..d + len].iter_mut().for_each(|u| *u = true);
+                blocks.push((a, d, len));
+                a += len;
+            }
+            _ => a += 1,
+        }
+    }
+
+    for (a, d, len) in blocks {
+        for (add, delete) in adds[a..a + len].iter().zip(&deletes[d..d + len]) {
+            let add_path = files[add.file].0.to_string();
+            let delete_path = files[delete.file].0.to_string();
+
+            files[add.file].1[add.line].moved = Some(MoveLink {
+                path: delete_path,
+                lineno: delete.lineno,
+            });
+            files[delete.file].1[delete.line].moved = Some(MoveLink {
+                path: add_path,
+                lineno: add.lineno,
+            });
+        }
+    }
+}
+
+fn alnum_count(block: &[Candidate]) -> usize {
+    block
+        .iter()
+        .map(|candidate| candidate.key.chars().filter(|c| c.is_alphanumeric()).count())
+        .sum()
+}
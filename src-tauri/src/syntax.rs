@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::OnceLock;
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
+
+/// Files larger than this are left unhighlighted
+const MAX_HIGHLIGHT_BYTES: usize = 1024 * 1024;
+
+/// Extensions the bundled grammars lack, mapped to the closest one they have
+const EXTENSION_ALIASES: &[(&str, &str)] = &[
+    ("ts", "js"),
+    ("tsx", "js"),
+    ("mts", "js"),
+    ("cts", "js"),
+    ("jsx", "js"),
+    ("mjs", "js"),
+    ("cjs", "js"),
+    ("svelte", "html"),
+    ("vue", "html"),
+    ("jsonc", "json"),
+];
+
+/// Token kind of each scope prefix. The innermost scope with a match decides, and within one scope
+/// the first matching prefix wins.
+const SCOPE_KINDS: &[(&str, &str)] = &[
+    ("comment", "comment"),
+    ("string", "string"),
+    ("constant.character.escape", "string"),
+    ("constant.numeric", "number"),
+    ("constant", "constant"),
+    ("keyword.operator", "operator"),
+    ("keyword", "keyword"),
+    ("storage", "keyword"),
+    ("variable.language", "keyword"),
+    ("entity.name.function", "function"),
+    ("support.function", "function"),
+    ("variable.function", "function"),
+    ("entity.name.tag", "tag"),
+    ("entity.name", "type"),
+    ("entity.other.inherited-class", "type"),
+    ("support.type", "type"),
+    ("support.class", "type"),
+    ("entity.other.attribute-name", "attribute"),
+];
+
+/// A syntax-highlighted range of a line, in UTF-16 code units like `Highlight`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SyntaxToken {
+    pub start: u32,
+    pub end: u32,
+    /// "comment", "string", "number", "constant", "operator", "keyword", "function", "type", "tag" or "attribute"
+    pub kind: String,
+}
+
+struct Grammars {
+    syntaxes: SyntaxSet,
+    kinds: Vec<(Scope, &'static str)>,
+}
+
+/// The bundled grammars, loaded on first use
+fn grammars() -> &'static Grammars {
+    static GRAMMARS: OnceLock<Grammars> = OnceLock::new();
+    GRAMMARS.get_or_init(|| Grammars {
+        syntaxes: SyntaxSet::load_defaults_newlines(),
+        kinds: SCOPE_KINDS
+            .iter()
+            .filter_map(|&(prefix, kind)| Scope::new(prefix).ok().map(|scope| (scope, kind)))
+            .collect(),
+    })
+}
+
+/// Syntax tokens of the first `line_count` lines of a file. The file is parsed from the top, so state
+/// that carries across lines (block comments, multi-line strings) is right wherever a hunk starts.
+/// `None` for files without a known grammar or too large to parse.
+pub fn highlight(path: &str, text: &str, line_count: usize) -> Option<Vec<Vec<SyntaxToken>>> {
+    if text.len() > MAX_HIGHLIGHT_BYTES || line_count == 0 {
+        return None;
+    }
+
+    let grammars = grammars();
+    let syntax = find_syntax(&grammars.syntaxes, path, text)?;
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+
+    let mut lines = Vec::new();
+    for line in text.split_inclusive('\n').take(line_count) {
+        let ops = state.parse_line(line, &grammars.syntaxes).ok()?;
+        let content_end = line.trim_end_matches(['\n', '\r']).len();
+
+        let mut tokens: Vec<SyntaxToken> = Vec::new();
+        let mut pos = 0;
+        let mut utf16_pos = 0;
+        let mut emit = |stack: &ScopeStack, to: usize, pos: &mut usize| {
+            let to = to.min(content_end);
+            if to <= *pos {
+                return;
+            }
+            let start = utf16_pos;
+            utf16_pos += line[*pos..to].encode_utf16().count() as u32;
+            *pos = to;
+
+            let Some(kind) = token_kind(&grammars.kinds, stack) else {
+                return;
+            };
+            match tokens.last_mut() {
+                Some(last) if last.kind == kind && last.end == start => last.end = utf16_pos,
+                _ => tokens.push(SyntaxToken {
+                    start,
+                    end: utf16_pos,
+                    kind: kind.to_string(),
+                }),
+            }
+        };
+
+        for (offset, op) in ops {
+            emit(&stack, offset, &mut pos);
+            stack.apply(&op).ok()?;
+        }
+        emit(&stack, content_end, &mut pos);
+        lines.push(tokens);
+    }
+
+    Some(lines)
+}
+
+/// Grammar for a path, by extension, then by file name (`Makefile`), then by the first line (shebangs)
+fn find_syntax<'a>(syntaxes: &'a SyntaxSet, path: &str, text: &str) -> Option<&'a SyntaxReference> {
+    let path = Path::new(path);
+    let name = path.file_name()?.to_string_lossy();
+    let extension = path.extension().map(|ext| {
+        let ext = ext.to_string_lossy().to_lowercase();
+        EXTENSION_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == ext)
+            .map_or(ext, |(_, target)| target.to_string())
+    });
+
+    extension
+        .and_then(|ext| syntaxes.find_syntax_by_extension(&ext))
+        .or_else(|| syntaxes.find_syntax_by_extension(&name))
+        .or_else(|| syntaxes.find_syntax_by_first_line(text.lines().next()?))
+        .filter(|syntax| syntax.name != "Plain Text")
+}
+
+fn token_kind(kinds: &[(Scope, &'static str)], stack: &ScopeStack) -> Option<&'static str> {
+    stack.as_slice().iter().rev().find_map(|&scope| {
+        kinds
+            .iter()
+            .find(|(prefix, _)| prefix.is_prefix_of(scope))
+            .map(|&(_, kind)| kind)
+    })
+}
@@ -0,0 +1,167 @@
+use crate::git_ops::DiffHunk;
+use std::path::Path;
+use tree_sitter::{Language, Node, Parser};
+
+/// Files larger than this are left to the line heuristics
+const MAX_PARSE_BYTES: usize = 1024 * 1024;
+
+/// Beyond this many hunks, reparsing the file once per hunk costs more than it is worth
+const MAX_HUNKS: usize = 100;
+
+/// Deeper trees than this are left to the line heuristics rather than risk the stack
+const MAX_DEPTH: usize = 512;
+
+/// One step of a depth-first walk over a syntax tree, without comments and whitespace
+#[derive(PartialEq)]
+enum Item<'a> {
+    Enter(u16),
+    Leave,
+    Text(&'a str),
+}
+
+/// Grammar of a file, by extension
+fn language(path: &str) -> Option<Language> {
+    let extension = Path::new(path).extension()?.to_string_lossy().to_lowercase();
+    let language = match extension.as_str() {
+        "rs" => tree_sitter_rust::LANGUAGE,
+        "js" | "jsx" | "mjs" | "cjs" => tree_sitter_javascript::LANGUAGE,
+        "ts" | "mts" | "cts" => tree_sitter_typescript::LANGUAGE_TYPESCRIPT,
+        "tsx" => tree_sitter_typescript::LANGUAGE_TSX,
+        "py" | "pyi" => tree_sitter_python::LANGUAGE,
+        "go" => tree_sitter_go::LANGUAGE,
+        "c" | "h" => tree_sitter_c::LANGUAGE,
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" | "hxx" => tree_sitter_cpp::LANGUAGE,
+        "java" => tree_sitter_java::LANGUAGE,
+        "cs" => tree_sitter_c_sharp::LANGUAGE,
+        "rb" => tree_sitter_ruby::LANGUAGE,
+        "json" => tree_sitter_json::LANGUAGE,
+        "css" => tree_sitter_css::LANGUAGE,
+        "sh" | "bash" => tree_sitter_bash::LANGUAGE,
+        _ => return None,
+    };
+    Some(language.into())
+}
+
+/// Whether the syntax tree of a file is the same on both sides apart from comments and whitespace.
+/// A new side that no longer parses cleanly is a real change (`a b` for `ab`). `None` when there is
+/// no grammar for the file, or the old side is too large or does not parse cleanly.
+pub fn file_equivalent(path: &str, old: &str, new: &str) -> Option<bool> {
+    let language = language(path)?;
+    let mut parser = Parser::new();
+    parser.set_language(&language).ok()?;
+
+    let old_items = flatten(&mut parser, old).ok()??;
+    compare(&old_items, flatten(&mut parser, new))
+}
+
+/// Whether each hunk leaves the syntax tree of the old side unchanged apart from comments and
+/// whitespace, judged by applying that hunk alone to the old side. `None` for hunks that cannot be
+/// judged, for the same reasons as `file_equivalent`.
+pub fn hunks_equivalent(path: &str, old: &str, new: &str, hunks: &[DiffHunk]) -> Vec<Option<bool>> {
+    let unknown = vec![None; hunks.len()];
+    let Some(language) = language(path).filter(|_| hunks.len() <= MAX_HUNKS) else {
+        return unknown;
+    };
+    let mut parser = Parser::new();
+    if parser.set_language(&language).is_err() {
+        return unknown;
+    }
+    let Ok(Some(old_items)) = flatten(&mut parser, old) else {
+        return unknown;
+    };
+
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    hunks
+        .iter()
+        .map(|hunk| {
+            let patched = apply_hunk(&old_lines, &new_lines, hunk)?;
+            compare(&old_items, flatten(&mut parser, &patched))
+        })
+        .collect()
+}
+
+/// The old side with the lines of one hunk replaced by their new-side counterparts. The hunk header
+/// is used rather than the hunk's lines, which may leave out whitespace changes that were ignored.
+fn apply_hunk(old_lines: &[&str], new_lines: &[&str], hunk: &DiffHunk) -> Option<String> {
+    // An empty side's start is the line *before* the change
+    let side_range = |start: u32, count: u32| {
+        let begin = if count == 0 { start } else { start.saturating_sub(1) } as usize;
+        begin..begin + count as usize
+    };
+    let old_range = side_range(hunk.old_start, hunk.old_lines);
+    let new_range = side_range(hunk.new_start, hunk.new_lines);
+
+    let mut patched = old_lines.get(..old_range.start)?.concat();
+    patched.push_str(&new_lines.get(new_range)?.concat());
+    patched.push_str(&old_lines.get(old_range.end..)?.concat());
+    Some(patched)
+}
+
+fn compare(old_items: &[Item], new_items: Result<Option<Vec<Item>>, SyntaxError>) -> Option<bool> {
+    match new_items {
+        Ok(new_items) => new_items.map(|new_items| new_items == old_items),
+        Err(SyntaxError) => Some(false),
+    }
+}
+
+/// The text parsed with syntax errors
+struct SyntaxError;
+
+/// Walk of the syntax tree of a text, `None` if it is too large or deep to compare
+fn flatten<'a>(parser: &mut Parser, text: &'a str) -> Result<Option<Vec<Item<'a>>>, SyntaxError> {
+    if text.len() > MAX_PARSE_BYTES {
+        return Ok(None);
+    }
+    let Some(tree) = parser.parse(text, None) else {
+        return Ok(None);
+    };
+    let root = tree.root_node();
+    if root.has_error() {
+        return Err(SyntaxError);
+    }
+
+    let mut items = Vec::new();
+    Ok(flatten_node(root, text, 0, &mut items).map(|_| items))
+}
+
+fn flatten_node<'a>(node: Node, text: &'a str, depth: usize, items: &mut Vec<Item<'a>>) -> Option<()> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    // Whitespace inside a string literal is content, so literals are compared as written
+    if node.child_count() == 0 || is_literal(node.kind()) {
+        items.push(Item::Text(&text[node.byte_range()]));
+        return Some(());
+    }
+
+    items.push(Item::Enter(node.kind_id()));
+    let mut end = node.start_byte();
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        push_gap(&text[end..child.start_byte()], items);
+        end = child.end_byte();
+        if !is_trivia(&child) {
+            flatten_node(child, text, depth + 1, items)?;
+        }
+    }
+    push_gap(&text[end..node.end_byte()], items);
+    items.push(Item::Leave);
+    Some(())
+}
+
+/// Text between the children of a node is normally whitespace; anything else (literal content some
+/// grammars leave out of their child nodes) is compared as written
+fn push_gap<'a>(gap: &'a str, items: &mut Vec<Item<'a>>) {
+    if !gap.trim().is_empty() {
+        items.push(Item::Text(gap));
+    }
+}
+
+fn is_trivia(node: &Node) -> bool {
+    node.is_extra() || node.kind().contains("comment")
+}
+
+fn is_literal(kind: &str) -> bool {
+    kind.contains("string") || kind.contains("heredoc") || kind == "text_block"
+}
@@ -2,7 +2,7 @@ import { get } from 'svelte/store';
 import {
   selectedFile, filteredFiles, repoPath, baseBranch, repoInfo,
   currentDiff, isLoading, viewMode, fileSearch, changedFiles,
-  showAdded, showModified, showDeleted, showCosmetic, allCollapsed,
+  showAdded, showModified, showDeleted, showCosmetic, showLowValue, allCollapsed,
   showHelp
 } from './stores';
 import { getFileDiff, selectFolder, getRepoInfo, getChangedFiles, watchRepo } from './tauri';
@@ -92,6 +92,10 @@ export function handleKeyboard(e: KeyboardEvent) {
       showCosmetic.update(v => !v);
       handleFilterChange();
       break;
+    case '5':
+      showLowValue.update(v => !v);
+      handleFilterChange();
+      break;
 
     // Tree
     case '[':
@@ -175,7 +179,7 @@ export async function refresh() {
 
   isLoading.set(true);
   try {
-    const files = await getChangedFiles(path, base);
+    const { files } = await getChangedFiles(path, base);
     changedFiles.set(files);
 
     // Preserve selection if the file still exists in the updated list
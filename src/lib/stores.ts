@@ -18,6 +18,8 @@ export const showAdded = writable<boolean>(true);
 export const showModified = writable<boolean>(true);
 export const showDeleted = writable<boolean>(true);
 export const showCosmetic = writable<boolean>(true);
+/** Generated, vendored and lockfile changes are hidden until asked for */
+export const showLowValue = writable<boolean>(false);
 export const allCollapsed = writable<boolean>(false);
 
 // Help modal
@@ -39,12 +41,17 @@ export function clearHover() {
   hoverContext.set(null);
 }
 
+export function isLowValue(file: ChangedFile): boolean {
+  return file.classification === 'generated' || file.classification === 'vendored' || file.classification === 'lockfile';
+}
+
 // Filtered files based on search and status toggles
 export const filteredFiles = derived(
-  [changedFiles, fileSearch, showAdded, showModified, showDeleted, showCosmetic],
-  ([$files, $search, $showAdded, $showModified, $showDeleted, $showCosmetic]) => {
+  [changedFiles, fileSearch, showAdded, showModified, showDeleted, showCosmetic, showLowValue],
+  ([$files, $search, $showAdded, $showModified, $showDeleted, $showCosmetic, $showLowValue]) => {
     return $files.filter((file) => {
       if (!$showCosmetic && file.is_cosmetic) return false;
+      if (!$showLowValue && isLowValue(file)) return false;
 
       const status = file.status;
       if (status === 'added' && !$showAdded) return false;
@@ -123,7 +130,8 @@ export const summary = derived(changedFiles, ($files) => {
   const modified = $files.filter(f => f.status === 'modified').length;
   const deleted = $files.filter(f => f.status === 'deleted').length;
   const cosmetic = $files.filter(f => f.is_cosmetic).length;
-  return { fileCount: $files.length, additions: totalAdditions, deletions: totalDeletions, added, modified, deleted, cosmetic };
+  const lowValue = $files.filter(isLowValue).length;
+  return { fileCount: $files.length, additions: totalAdditions, deletions: totalDeletions, added, modified, deleted, cosmetic, lowValue };
 });
 
 export const filteredSummary = derived(filteredFiles, ($files) => {
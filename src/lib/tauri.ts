@@ -1,18 +1,144 @@
 import { invoke } from '@tauri-apps/api/core';
 import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 import { open } from '@tauri-apps/plugin-dialog';
-import type { ChangedFile, FileDiff, RepoInfo } from './types';
+import type {
+  ChangeSet,
+  CommitInfo,
+  CompareOptions,
+  DiffSide,
+  DiffWindow,
+  FileBlobs,
+  FileDiff,
+  FileLines,
+  RepoInfo,
+  SubmoduleSummary
+} from './types';
 
 export async function getRepoInfo(repoPath: string): Promise<RepoInfo> {
   return invoke<RepoInfo>('get_repo_info', { repoPath });
 }
 
-export async function getChangedFiles(repoPath: string, baseBranch: string): Promise<ChangedFile[]> {
-  return invoke<ChangedFile[]>('get_changed_files', { repoPath, baseBranch });
+export async function getChangedFiles(
+  repoPath: string,
+  baseBranch: string,
+  options?: CompareOptions
+): Promise<ChangeSet> {
+  return invoke<ChangeSet>('get_changed_files', { repoPath, baseBranch, options });
 }
 
-export async function getFileDiff(repoPath: string, filePath: string, baseBranch: string): Promise<FileDiff> {
-  return invoke<FileDiff>('get_file_diff', { repoPath, filePath, baseBranch });
+export async function getFileDiff(
+  repoPath: string,
+  filePath: string,
+  baseBranch: string,
+  options?: CompareOptions,
+  window?: DiffWindow
+): Promise<FileDiff> {
+  return invoke<FileDiff>('get_file_diff', { repoPath, filePath, baseBranch, window, options });
+}
+
+export async function getChangedFilesBetween(
+  repoPath: string,
+  baseRev: string,
+  headRev: string,
+  options?: CompareOptions
+): Promise<ChangeSet> {
+  return invoke<ChangeSet>('get_changed_files_between', { repoPath, baseRev, headRev, options });
+}
+
+export async function getFileDiffBetween(
+  repoPath: string,
+  filePath: string,
+  baseRev: string,
+  headRev: string,
+  options?: CompareOptions,
+  window?: DiffWindow
+): Promise<FileDiff> {
+  return invoke<FileDiff>('get_file_diff_between', { repoPath, filePath, baseRev, headRev, window, options });
+}
+
+export async function getFileLines(
+  repoPath: string,
+  filePath: string,
+  baseBranch: string,
+  side: DiffSide,
+  start: number,
+  end: number,
+  options?: CompareOptions
+): Promise<FileLines> {
+  return invoke<FileLines>('get_file_lines', { repoPath, filePath, baseBranch, side, start, end, options });
+}
+
+export async function getFileLinesBetween(
+  repoPath: string,
+  filePath: string,
+  baseRev: string,
+  headRev: string,
+  side: DiffSide,
+  start: number,
+  end: number,
+  options?: CompareOptions
+): Promise<FileLines> {
+  return invoke<FileLines>('get_file_lines_between', { repoPath, filePath, baseRev, headRev, side, start, end, options });
+}
+
+export async function getFileBlobs(
+  repoPath: string,
+  filePath: string,
+  baseBranch: string,
+  maxBytes?: number,
+  options?: CompareOptions
+): Promise<FileBlobs> {
+  return invoke<FileBlobs>('get_file_blobs', { repoPath, filePath, baseBranch, maxBytes, options });
+}
+
+export async function getFileBlobsBetween(
+  repoPath: string,
+  filePath: string,
+  baseRev: string,
+  headRev: string,
+  maxBytes?: number,
+  options?: CompareOptions
+): Promise<FileBlobs> {
+  return invoke<FileBlobs>('get_file_blobs_between', { repoPath, filePath, baseRev, headRev, maxBytes, options });
+}
+
+export async function getBranchCommits(repoPath: string, baseBranch: string): Promise<CommitInfo[]> {
+  return invoke<CommitInfo[]>('get_branch_commits', { repoPath, baseBranch });
+}
+
+export async function getCommitsBetween(repoPath: string, baseRev: string, headRev: string): Promise<CommitInfo[]> {
+  return invoke<CommitInfo[]>('get_commits_between', { repoPath, baseRev, headRev });
+}
+
+/** Changes of the commits fromRev through toRev; pass the same oid twice for a single commit */
+export async function getCommitRangeFiles(
+  repoPath: string,
+  fromRev: string,
+  toRev: string,
+  options?: CompareOptions
+): Promise<ChangeSet> {
+  return invoke<ChangeSet>('get_commit_range_files', { repoPath, fromRev, toRev, options });
+}
+
+export async function getCommitRangeFileDiff(
+  repoPath: string,
+  filePath: string,
+  fromRev: string,
+  toRev: string,
+  options?: CompareOptions,
+  window?: DiffWindow
+): Promise<FileDiff> {
+  return invoke<FileDiff>('get_commit_range_file_diff', { repoPath, filePath, fromRev, toRev, window, options });
+}
+
+export async function getSubmoduleSummary(
+  repoPath: string,
+  submodulePath: string,
+  oldOid: string,
+  newOid: string,
+  options?: CompareOptions
+): Promise<SubmoduleSummary> {
+  return invoke<SubmoduleSummary>('get_submodule_summary', { repoPath, submodulePath, oldOid, newOid, options });
 }
 
 export async function openInEditor(repoPath: string, filePath: string): Promise<void> {
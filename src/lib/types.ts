@@ -1,16 +1,105 @@
 export interface ChangedFile {
   path: string;
-  status: 'added' | 'deleted' | 'modified' | 'renamed' | 'copied' | 'typechange' | 'unknown';
+  status: 'added' | 'deleted' | 'modified' | 'renamed' | 'copied' | 'typechange' | 'mode-changed' | 'unknown';
   additions: number;
   deletions: number;
   is_cosmetic: boolean;
+  cosmetic_reason: CosmeticReason | null;
+  old_path: string | null;
+  similarity: number | null;
+  layers: ChangeLayer[];
+  /** Set when the path is a submodule rather than a regular file */
+  submodule: SubmoduleChange | null;
+  /** Octal git file mode, e.g. '100644', '100755', '120000' (symlink); null where the file does not exist */
+  old_mode: string | null;
+  new_mode: string | null;
+  /** Encoding each side is stored in, e.g. 'UTF-8', 'UTF-16LE', 'windows-1252'; null for binary content or a missing side */
+  old_encoding: string | null;
+  new_encoding: string | null;
+  old_line_ending: LineEnding | null;
+  new_line_ending: LineEnding | null;
+  /** The line ending convention differs between the sides */
+  line_endings_changed: boolean;
+  /** Set on the sides stored in Git LFS */
+  old_lfs: LfsInfo | null;
+  new_lfs: LfsInfo | null;
+  classification: FileClassification;
 }
 
+export type FileClassification = 'generated' | 'vendored' | 'lockfile' | 'test' | 'documentation' | 'source';
+
+export interface LfsInfo {
+  /** SHA-256 of the real content */
+  oid: string;
+  size: number;
+  /** The object is in the local LFS store and its content was compared; otherwise the pointer file stands in for it */
+  available: boolean;
+}
+
+export type LineEnding = 'lf' | 'crlf' | 'mixed';
+
+/** Why a change counts as cosmetic; 'syntax' means the syntax tree is unchanged, 'mixed' that the lines of a hunk differ */
+export type CosmeticReason =
+  | 'whitespace'
+  | 'comment'
+  | 'case'
+  | 'rewrap'
+  | 'line-ending'
+  | 'moved'
+  | 'syntax'
+  | 'mixed';
+
+export interface SubmoduleChange {
+  /** null when the submodule was added */
+  old_oid: string | null;
+  /** null when the submodule was removed */
+  new_oid: string | null;
+}
+
+export type ChangeLayer = 'committed' | 'staged' | 'unstaged' | 'untracked';
+
 export interface DiffLine {
   content: string;
   line_type: 'add' | 'delete' | 'context';
   old_lineno: number | null;
   new_lineno: number | null;
+  highlights: Highlight[];
+  moved: MoveLink | null;
+  /** Syntax highlighting of `content`, parsed from the whole file so multi-line constructs are right */
+  tokens: SyntaxToken[];
+  /** Set on the changed lines of cosmetic hunks */
+  cosmetic_reason: CosmeticReason | null;
+}
+
+/** Counterpart of a moved line: the added line for a deletion (new-side line number), the deleted line for an addition (old-side line number) */
+export interface MoveLink {
+  path: string;
+  lineno: number;
+}
+
+/** Changed range within a line, in UTF-16 code units (usable with String.prototype.slice) */
+export interface Highlight {
+  start: number;
+  end: number;
+}
+
+export type SyntaxKind =
+  | 'comment'
+  | 'string'
+  | 'number'
+  | 'constant'
+  | 'operator'
+  | 'keyword'
+  | 'function'
+  | 'type'
+  | 'tag'
+  | 'attribute';
+
+/** Syntax-highlighted range within a line, in UTF-16 code units like `Highlight` */
+export interface SyntaxToken {
+  start: number;
+  end: number;
+  kind: SyntaxKind;
 }
 
 export interface DiffHunk {
@@ -20,13 +109,141 @@ export interface DiffHunk {
   new_lines: number;
   lines: DiffLine[];
   is_cosmetic: boolean;
+  cosmetic_reason: CosmeticReason | null;
+  /** Every change in the hunk only converts line endings */
+  line_endings_changed: boolean;
+  layer: ChangeLayer | 'combined';
+  /** Leading lines left out because they fall before the requested window */
+  line_offset: number;
+  /** Commit that introduced the hunk when CompareOptions.commits is set */
+  commit: string | null;
+}
+
+export type CompareMode = 'three-dot' | 'two-dot' | 'first-parent';
+
+export type DiffView = 'layered' | 'combined';
+
+export interface CompareOptions {
+  mode?: CompareMode;
+  view?: DiffView;
+  detect_renames?: boolean;
+  similarity_threshold?: number;
+  context_lines?: number;
+  ignore_whitespace?: boolean;
+  ignore_whitespace_change?: boolean;
+  ignore_whitespace_eol?: boolean;
+  ignore_blank_lines?: boolean;
+  intraline?: IntralineMode;
+  algorithm?: DiffAlgorithm;
+  detect_moves?: boolean;
+  moves_are_cosmetic?: boolean;
+  /** Above this many lines a file diff only returns its totals; 0 loads it anyway */
+  max_diff_lines?: number;
+  /** Only the changes these commits of the compared range introduced, each against its first parent */
+  commits?: string[];
+  /** Convert CRLF to LF before diffing, so line ending conversions leave no hunks */
+  normalize_line_endings?: boolean;
+  /** Attach syntax tokens to diff lines (default true) */
+  syntax_highlight?: boolean;
+  /** Judge cosmetic changes by comparing syntax trees, for languages with a bundled grammar */
+  syntax_aware_cosmetic?: boolean;
+}
+
+/** Lines offset..offset + limit of a file diff, counting the lines of all hunks in order */
+export interface DiffWindow {
+  offset: number;
+  limit: number;
+}
+
+export type IntralineMode = 'off' | 'word' | 'char';
+
+/** 'auto' follows the repository's diff.algorithm setting */
+export type DiffAlgorithm = 'auto' | 'myers' | 'minimal' | 'patience' | 'histogram';
+
+export interface ChangeSet {
+  files: ChangedFile[];
+  mode: CompareMode;
+  /** All zeros when the diff starts from nothing, for a range that begins at a root commit */
+  base_oid: string;
+  head_oid: string;
 }
 
 export interface FileDiff {
   path: string;
+  old_path: string | null;
   hunks: DiffHunk[];
   is_binary: boolean;
   is_cosmetic: boolean;
+  /** Number of cosmetic hunks per reason, over the whole diff */
+  cosmetic_reasons: Partial<Record<CosmeticReason, number>>;
+  mode: CompareMode;
+  /** All zeros when the diff starts from nothing, like `ChangeSet.base_oid` */
+  base_oid: string;
+  total_hunks: number;
+  total_lines: number;
+  is_truncated: boolean;
+  old_mode: string | null;
+  new_mode: string | null;
+  /** Link target on each side that is a symlink */
+  old_symlink_target: string | null;
+  new_symlink_target: string | null;
+  old_encoding: string | null;
+  new_encoding: string | null;
+  old_line_ending: LineEnding | null;
+  new_line_ending: LineEnding | null;
+  line_endings_changed: boolean;
+  old_lfs: LfsInfo | null;
+  new_lfs: LfsInfo | null;
+}
+
+export type DiffSide = 'old' | 'new';
+
+export interface FileLines {
+  path: string;
+  side: DiffSide;
+  total_lines: number;
+  lines: DiffLine[];
+}
+
+export interface CommitInfo {
+  oid: string;
+  short_oid: string;
+  summary: string;
+  author_name: string;
+  author_email: string;
+  /** Author time in seconds since the Unix epoch */
+  time: number;
+  /** Paths changed relative to the first parent */
+  files: string[];
+}
+
+export interface SubmoduleSummary {
+  /** Working directory of the submodule, usable as repoPath for further comparisons */
+  repo_path: string;
+  /** Commits the pointer moved forward over, oldest first */
+  commits: CommitInfo[];
+  /** Commits the pointer moved back over */
+  removed_commits: CommitInfo[];
+  changes: ChangeSet;
+}
+
+export interface BlobInfo {
+  size: number;
+  mime_type: string;
+  width: number | null;
+  height: number | null;
+  /** Base64 encoded content; null when the blob is larger than the requested cap */
+  data: string | null;
+}
+
+/** Both versions of a file; a side is null where the file does not exist */
+export interface FileBlobs {
+  path: string;
+  old_path: string | null;
+  old: BlobInfo | null;
+  new: BlobInfo | null;
+  old_lfs: LfsInfo | null;
+  new_lfs: LfsInfo | null;
 }
 
 export interface BranchInfo {